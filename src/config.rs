@@ -1,8 +1,11 @@
 pub use crate::utils::{is_fd_valid, is_namespace_supported};
 use anyhow::{Error, Result, anyhow};
 use clap::{ArgGroup, Args, Parser};
-use nix::sched::CloneFlags;
-use std::{path::PathBuf, str::FromStr};
+use nix::{mount::MsFlags, sched::CloneFlags};
+use std::{
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
 
 // TODO: Following sections:
 // const HEADING_SECURITY: &str = "Security";
@@ -17,6 +20,7 @@ const HEADING_ENVIRONMENT: &str = "Environment";
 const HEADING_DEBUG: &str = "Debug";
 
 const FD_PREFIX: &str = "fd=";
+const TRY_SUFFIX: &str = "-try";
 
 #[derive(Parser, Debug, Clone)]
 #[command(name = "Enclosure", about = "Unprivileged Sandboxing Tool")]
@@ -30,8 +34,11 @@ pub struct Config {
     #[command(flatten)]
     pub user: UserOptions,
 
-    #[arg(long, value_parser = MountEntry::from_str)]
-    pub mount: Vec<MountEntry>,
+    #[arg(long, value_parser = MountSpec::from_str, help_heading = HEADING_MOUNT)]
+    pub mount: Vec<MountSpec>,
+
+    #[command(flatten)]
+    pub mount_opts: MountOptions,
 
     #[command(flatten)]
     pub env: EnvOptions,
@@ -50,6 +57,88 @@ impl Config {
     pub fn parse_clone_flags(&self) -> Result<CloneFlags, Error> {
         self.namespace.parse()
     }
+
+    /// Normalizes the parsed arguments before they're handed to the sandbox.
+    pub fn prepare(&mut self) {
+        self.namespace.prepare();
+    }
+
+    /// `(entry index, host path)` of every path bind w/ `propagation=shared`.
+    ///
+    /// Optional entries whose source is missing are left out, they're skipped later on.
+    pub fn shared_binds(&self) -> impl Iterator<Item = (usize, &Path)> {
+        self.mount
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, spec)| match spec {
+                MountSpec {
+                    entry:
+                        MountEntry::Bind {
+                            src: MountSource::Path { target, .. },
+                            ..
+                        },
+                    optional,
+                    propagation: Some(Propagation::Shared),
+                    ..
+                } if !optional || target.symlink_metadata().is_ok() => {
+                    Some((idx, target.as_path()))
+                }
+                _ => None,
+            })
+    }
+
+    /// `(entry index, host path, mapping)` of every source that's ID-mapped before clone().
+    ///
+    /// Overlays contribute each lowerdir, and the common parent of upperdir and workdir
+    /// since overlayfs wants those two on the same mount.
+    pub fn idmapped_sources(&self) -> Vec<(usize, PathBuf, &IdMap)> {
+        let mut sources = Vec::new();
+
+        for (idx, spec) in self.mount.iter().enumerate() {
+            let Some(idmap) = &spec.idmap else {
+                continue;
+            };
+
+            let paths = match &spec.entry {
+                MountEntry::Bind {
+                    src: MountSource::Path { target, .. },
+                    ..
+                } => vec![target.clone()],
+                MountEntry::Overlay {
+                    lowerdir,
+                    upperdir,
+                    workdir,
+                    ..
+                } => lowerdir
+                    .iter()
+                    .cloned()
+                    .chain(
+                        upperdir
+                            .as_deref()
+                            .zip(workdir.as_deref())
+                            .map(|(upper, work)| common_parent(upper, work)),
+                    )
+                    .collect(),
+                _ => continue,
+            };
+
+            if spec.optional && paths.iter().any(|path| path.symlink_metadata().is_err()) {
+                continue;
+            }
+            sources.extend(paths.into_iter().map(|path| (idx, path, idmap)));
+        }
+
+        sources
+    }
+}
+
+/// Deepest directory containing both `a` and `b`.
+pub fn common_parent(a: &Path, b: &Path) -> PathBuf {
+    a.components()
+        .zip(b.components())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a)
+        .collect()
 }
 
 #[derive(Args, Debug, Clone)]
@@ -139,6 +228,18 @@ pub struct NamespaceOptions {
 }
 
 impl NamespaceOptions {
+    /// Expands `--unshare-all` so per-namespace checks (e.g. `unshare_pid`) see it.
+    fn prepare(&mut self) {
+        if self.unshare_all {
+            self.unshare_ipc = true;
+            self.unshare_pid = true;
+            self.unshare_net = true;
+            self.unshare_uts = true;
+            self.unshare_cgroup = true;
+            self.unshare_user = true;
+        }
+    }
+
     fn mappings(&self) -> [(bool, CloneFlags); 9] {
         [
             (self.unshare_files, CloneFlags::CLONE_FILES),
@@ -241,10 +342,90 @@ pub struct UserOptions {
     pub hostname: Option<String>,
 }
 
+#[derive(Args, Debug, Clone)]
+pub struct MountOptions {
+    #[arg(
+        long,
+        help = "Don't mask or remount read-only sensitive procfs entries",
+        help_heading = HEADING_MOUNT
+    )]
+    pub no_proc_hardening: bool,
+
+    #[arg(
+        long,
+        help = "Mask this procfs entry, relative to the proc mount (replaces the defaults)",
+        value_name = "ENTRY",
+        value_parser = parse_proc_entry,
+        help_heading = HEADING_MOUNT
+    )]
+    pub proc_mask: Vec<PathBuf>,
+
+    #[arg(
+        long,
+        help = "When /usr is mounted, recreate the host's /bin, /sbin, /lib and /lib64 links into it",
+        help_heading = HEADING_MOUNT
+    )]
+    pub merged_usr: bool,
+
+    #[arg(
+        long,
+        help = "Directory the staging tmpfs is mounted over, only inside the sandbox (default: a private directory in /tmp, then $XDG_RUNTIME_DIR)",
+        value_name = "DIR",
+        help_heading = HEADING_MOUNT
+    )]
+    pub staging_dir: Option<PathBuf>,
+
+    #[arg(
+        long,
+        help = "Make the new root and every mount in it read-only, except entries passed w/ 'rw'",
+        help_heading = HEADING_MOUNT
+    )]
+    pub ro_root: bool,
+}
+
+impl MountOptions {
+    /// Entries hidden behind '/dev/null' (files) or an empty read-only tmpfs (dirs).
+    const DEFAULT_PROC_MASK: [&str; 10] = [
+        "acpi",
+        "asound",
+        "kcore",
+        "keys",
+        "latency_stats",
+        "sched_debug",
+        "scsi",
+        "sysrq-trigger",
+        "timer_list",
+        "timer_stats",
+    ];
+
+    pub fn proc_mask(&self) -> Vec<PathBuf> {
+        match self.proc_mask.is_empty() {
+            true => Self::DEFAULT_PROC_MASK.iter().map(PathBuf::from).collect(),
+            false => self.proc_mask.clone(),
+        }
+    }
+}
+
+fn parse_proc_entry(input: &str) -> Result<PathBuf> {
+    let path = Path::new(input.trim().trim_start_matches('/'));
+
+    if path.as_os_str().is_empty() {
+        return Err(anyhow!("procfs entry cannot be empty"));
+    }
+
+    if !path.components().all(|c| matches!(c, Component::Normal(_))) {
+        return Err(anyhow!(
+            "procfs entry must stay inside the proc mount: '{input}'"
+        ));
+    }
+
+    Ok(path.to_path_buf())
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum ParseMountError {
     #[error(
-        "unknown mount kind '{kind}' (valid: bind, dev, dir, file, mqueue, overlay, proc, symlink, tmpfs)"
+        "unknown mount kind '{kind}' (valid: bind, cgroup, chmod, dev, devpts, dir, file, mqueue, overlay, proc, remount, symlink, sysfs, tmpfs; append '-try' to skip missing sources)"
     )]
     UnknownKind { kind: String },
 
@@ -323,16 +504,19 @@ pub enum MountEntry {
         fd: i32,
         dest: PathBuf,
         mode: Option<OctalPermissions>,
+        read_only: bool,
+        sealed: bool,
     },
     Symlink {
         target: PathBuf,
         link: PathBuf,
     },
+    Chmod(ChmodPair),
 
     // Overlay
     Overlay {
         dest: PathBuf,
-        lowerdir: PathBuf,
+        lowerdir: Vec<PathBuf>,
         upperdir: Option<PathBuf>,
         workdir: Option<PathBuf>,
     },
@@ -341,17 +525,376 @@ pub enum MountEntry {
     Proc {
         dest: PathBuf,
     },
+    Cgroup {
+        dest: PathBuf,
+        mode: Mode,
+    },
     Dev {
         dest: PathBuf,
     },
+    Devpts {
+        dest: PathBuf,
+    },
+    Sysfs {
+        dest: PathBuf,
+    },
     Tmpfs {
         dest: PathBuf,
-        size_kb: Option<usize>,
+        size: Option<Size>,
         permission: Option<OctalPermissions>,
+        nr_inodes: Option<u64>,
+        uid: Option<u32>,
+        gid: Option<u32>,
     },
     Mqueue {
         dest: PathBuf,
     },
+
+    // Runs once every other entry is in place
+    Remount {
+        dest: PathBuf,
+        recursive: bool,
+    },
+}
+
+/// A mount entry along w/ the modifiers every kind accepts.
+#[derive(Debug, Clone)]
+pub struct MountSpec {
+    pub entry: MountEntry,
+
+    /// Skip the entry when its source is missing (`<kind>-try:<args>`).
+    pub optional: bool,
+
+    /// Propagation type set on the mount once it's created (`propagation=<type>`).
+    pub propagation: Option<Propagation>,
+
+    /// ID mapping of the host sources, set up by the parent (`idmap[=<userns>]`).
+    pub idmap: Option<IdMap>,
+
+    /// Attributes set on the mount and its submounts once it's created.
+    pub attrs: MountAttrs,
+
+    /// Passed `rw` explicitly, so `--ro-root` leaves the mount writable.
+    pub writable: bool,
+}
+
+impl FromStr for MountSpec {
+    type Err = ParseMountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (kind, rest) = s.split_once(':').ok_or_else(|| {
+            ParseMountError::syntax("mount", "<kind>:<args>", "missing kind prefix")
+        })?;
+
+        let (kind, optional) = match kind.strip_suffix(TRY_SUFFIX) {
+            Some(kind) => (kind, true),
+            None => (kind, false),
+        };
+
+        // Options are always trailing, the kind-specific ones are left for `MountEntry`
+        let (args, opts) = rest.split_once(',').unwrap_or((rest, ""));
+        let mut propagation = None;
+        let mut idmap = None;
+        let mut attrs = MountAttrs::default();
+        let mut writable = false;
+        let mut remaining = vec![args];
+
+        for opt in opts.split(',').filter(|s| !s.trim().is_empty()) {
+            match opt.trim().strip_prefix("propagation=") {
+                Some(_) if propagation.is_some() => {
+                    return Err(ParseMountError::option(
+                        "mount",
+                        "duplicate 'propagation' passed",
+                    ));
+                }
+                Some(value) => propagation = Some(value.parse::<Propagation>()?),
+                None => match IdMap::parse_option(opt.trim())? {
+                    Some(_) if idmap.is_some() => {
+                        return Err(ParseMountError::option("mount", "duplicate 'idmap' passed"));
+                    }
+                    Some(parsed) => idmap = Some(parsed),
+                    None if attrs.parse_option(opt.trim())? => {}
+                    None if opt.trim() == "rw" => {
+                        writable = true;
+                        // Binds and cgroups take 'rw' as their mode, every other kind only knows it here
+                        if matches!(kind, BindMount::KIND | CgroupMount::KIND) {
+                            remaining.push(opt);
+                        }
+                    }
+                    None => remaining.push(opt),
+                },
+            }
+        }
+
+        let entry = format!("{kind}:{}", remaining.join(",")).parse::<MountEntry>()?;
+
+        if (propagation.is_some() || !attrs.is_empty()) && !entry.creates_mount() {
+            return Err(ParseMountError::option(
+                entry.kind(),
+                "'propagation' and mount attributes need an entry that creates a mount",
+            ));
+        }
+
+        if writable && (!entry.creates_mount() || matches!(entry, MountEntry::Remount { .. })) {
+            return Err(ParseMountError::option(
+                entry.kind(),
+                "'rw' needs an entry that creates a writable mount",
+            ));
+        }
+
+        if attrs.nodev
+            && let MountEntry::Bind {
+                src: MountSource::Path {
+                    mount_dev: true, ..
+                },
+                ..
+            } = entry
+        {
+            return Err(ParseMountError::option(
+                "bind",
+                "'dev' and 'nodev' are mutually exclusive",
+            ));
+        }
+
+        if idmap.is_some() {
+            match &entry {
+                MountEntry::Bind {
+                    src: MountSource::Path { .. },
+                    ..
+                } if propagation == Some(Propagation::Shared) => {
+                    return Err(ParseMountError::option(
+                        "bind",
+                        "'idmap' and 'propagation=shared' are mutually exclusive",
+                    ));
+                }
+                // Their common parent is what gets ID-mapped, '/' would be the whole host
+                MountEntry::Overlay {
+                    upperdir: Some(upper),
+                    workdir: Some(work),
+                    ..
+                } if common_parent(upper, work).parent().is_none() => {
+                    return Err(ParseMountError::option(
+                        "overlay",
+                        "'idmap' needs upperdir and workdir under a shared directory other than '/'",
+                    ));
+                }
+                MountEntry::Bind {
+                    src: MountSource::Path { .. },
+                    ..
+                }
+                | MountEntry::Overlay { .. } => {}
+                entry => {
+                    return Err(ParseMountError::option(
+                        entry.kind(),
+                        "'idmap' needs a bind w/ a path source or an overlay",
+                    ));
+                }
+            }
+        }
+
+        Ok(MountSpec {
+            entry,
+            optional,
+            propagation,
+            idmap,
+            attrs,
+            writable,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Propagation {
+    Private,
+    Slave,
+    Shared,
+    Unbindable,
+}
+
+impl FromStr for Propagation {
+    type Err = ParseMountError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "private" => Ok(Self::Private),
+            "slave" => Ok(Self::Slave),
+            "shared" => Ok(Self::Shared),
+            "unbindable" => Ok(Self::Unbindable),
+            value => Err(ParseMountError::option(
+                "mount",
+                format!(
+                    "unknown propagation '{value}' (valid: private, slave, shared, unbindable)"
+                ),
+            )),
+        }
+    }
+}
+
+/// User namespace whose mapping is applied to the sources of an entry.
+///
+/// IDs on disk are read as IDs inside that namespace, e.g. w/ `Sandbox` a file owned by
+/// uid 0 belongs to the sandbox's uid 0, and what it creates is stored as uid 0.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdMap {
+    /// Same mapping as the sandbox's own user namespace.
+    Sandbox,
+    /// Caller-supplied namespace, e.g. '/proc/<pid>/ns/user'.
+    Userns(PathBuf),
+}
+
+impl IdMap {
+    /// Returns `None` if `opt` isn't an `idmap` option.
+    fn parse_option(opt: &str) -> Result<Option<Self>, ParseMountError> {
+        match opt.split_once('=') {
+            None if opt == "idmap" => Ok(Some(Self::Sandbox)),
+            Some(("idmap", path)) => match PathBuf::from(path.trim()) {
+                path if path.is_absolute() => Ok(Some(Self::Userns(path))),
+                _ => Err(ParseMountError::option(
+                    "mount",
+                    "'idmap' takes an absolute path to a user namespace",
+                )),
+            },
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Options every mount kind accepts, applied w/ `mount_setattr(2)` so submounts are covered too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MountAttrs {
+    pub noexec: bool,
+    pub nosuid: bool,
+    pub nodev: bool,
+    pub nosymfollow: bool,
+    pub atime: Option<Atime>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Atime {
+    Relatime,
+    Noatime,
+    Strictatime,
+}
+
+impl MountAttrs {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Returns `false` if `opt` isn't a generic attribute.
+    fn parse_option(&mut self, opt: &str) -> Result<bool, ParseMountError> {
+        let flag = match opt {
+            "noexec" => &mut self.noexec,
+            "nosuid" => &mut self.nosuid,
+            "nodev" => &mut self.nodev,
+            "nosymfollow" => &mut self.nosymfollow,
+            "relatime" | "noatime" | "strictatime" => {
+                if self.atime.is_some() {
+                    return Err(ParseMountError::option(
+                        "mount",
+                        "only one of 'relatime', 'noatime' or 'strictatime' can be passed",
+                    ));
+                }
+                self.atime = Some(match opt {
+                    "relatime" => Atime::Relatime,
+                    "noatime" => Atime::Noatime,
+                    _ => Atime::Strictatime,
+                });
+                return Ok(true);
+            }
+            _ => return Ok(false),
+        };
+
+        if *flag {
+            return Err(ParseMountError::option(
+                "mount",
+                format!("duplicate '{opt}' passed"),
+            ));
+        }
+        *flag = true;
+        Ok(true)
+    }
+}
+
+impl Propagation {
+    pub fn flags(&self) -> MsFlags {
+        match self {
+            Self::Private => MsFlags::MS_PRIVATE,
+            Self::Slave => MsFlags::MS_SLAVE,
+            Self::Shared => MsFlags::MS_SHARED,
+            Self::Unbindable => MsFlags::MS_UNBINDABLE,
+        }
+    }
+}
+
+impl MountEntry {
+    /// Path of the entry inside the new root.
+    pub fn dest(&self) -> &Path {
+        match self {
+            MountEntry::Bind { dest, .. }
+            | MountEntry::Cgroup { dest, .. }
+            | MountEntry::Dev { dest }
+            | MountEntry::Devpts { dest }
+            | MountEntry::File { dest, .. }
+            | MountEntry::Mqueue { dest }
+            | MountEntry::Overlay { dest, .. }
+            | MountEntry::Proc { dest }
+            | MountEntry::Remount { dest, .. }
+            | MountEntry::Sysfs { dest }
+            | MountEntry::Tmpfs { dest, .. } => dest,
+            MountEntry::Dir { path, .. } | MountEntry::Chmod(ChmodPair { path, .. }) => path,
+            MountEntry::Symlink { link, .. } => link,
+        }
+    }
+
+    pub fn dest_mut(&mut self) -> &mut PathBuf {
+        match self {
+            MountEntry::Bind { dest, .. }
+            | MountEntry::Cgroup { dest, .. }
+            | MountEntry::Dev { dest }
+            | MountEntry::Devpts { dest }
+            | MountEntry::File { dest, .. }
+            | MountEntry::Mqueue { dest }
+            | MountEntry::Overlay { dest, .. }
+            | MountEntry::Proc { dest }
+            | MountEntry::Remount { dest, .. }
+            | MountEntry::Sysfs { dest }
+            | MountEntry::Tmpfs { dest, .. } => dest,
+            MountEntry::Dir { path, .. } | MountEntry::Chmod(ChmodPair { path, .. }) => path,
+            MountEntry::Symlink { link, .. } => link,
+        }
+    }
+
+    /// The `<kind>` prefix the entry was parsed from.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            MountEntry::Bind { .. } => BindMount::KIND,
+            MountEntry::Cgroup { .. } => CgroupMount::KIND,
+            MountEntry::Chmod(_) => ChmodMount::KIND,
+            MountEntry::Dev { .. } => DevMount::KIND,
+            MountEntry::Devpts { .. } => DevptsMount::KIND,
+            MountEntry::Dir { .. } => DirMount::KIND,
+            MountEntry::File { .. } => FileMount::KIND,
+            MountEntry::Mqueue { .. } => MQueueMount::KIND,
+            MountEntry::Overlay { .. } => OverlayMount::KIND,
+            MountEntry::Proc { .. } => ProcMount::KIND,
+            MountEntry::Remount { .. } => RemountMount::KIND,
+            MountEntry::Symlink { .. } => SymlinkMount::KIND,
+            MountEntry::Sysfs { .. } => SysfsMount::KIND,
+            MountEntry::Tmpfs { .. } => TmpfsMount::KIND,
+        }
+    }
+
+    /// Whether the entry puts a mount at `dest()`, rather than just creating a file.
+    pub fn creates_mount(&self) -> bool {
+        !matches!(
+            self,
+            MountEntry::Chmod(_)
+                | MountEntry::Dir { .. }
+                | MountEntry::File { .. }
+                | MountEntry::Symlink { .. }
+        )
+    }
 }
 
 impl FromStr for MountEntry {
@@ -365,14 +908,19 @@ impl FromStr for MountEntry {
 
         match kind {
             BindMount::KIND => BindMount::parse(rest),
+            CgroupMount::KIND => CgroupMount::parse(rest),
+            ChmodMount::KIND => ChmodMount::parse(rest),
             DevMount::KIND => DevMount::parse(rest),
+            DevptsMount::KIND => DevptsMount::parse(rest),
             DirMount::KIND => DirMount::parse(rest),
             FileMount::KIND => FileMount::parse(rest),
             MQueueMount::KIND => MQueueMount::parse(rest),
-            OverlayMount::KIND => todo!(),
+            OverlayMount::KIND => OverlayMount::parse(rest),
             ProcMount::KIND => ProcMount::parse(rest),
+            RemountMount::KIND => RemountMount::parse(rest),
             SymlinkMount::KIND => SymlinkMount::parse(rest),
-            TmpfsMount::KIND => todo!(),
+            SysfsMount::KIND => SysfsMount::parse(rest),
+            TmpfsMount::KIND => TmpfsMount::parse(rest),
             _ => Err(ParseMountError::UnknownKind {
                 kind: kind.to_owned(),
             }),
@@ -470,6 +1018,63 @@ impl MountParser for BindMount {
     }
 }
 
+struct CgroupMount;
+
+impl MountParser for CgroupMount {
+    const KIND: &'static str = "cgroup";
+    const SYNTAX: &'static str = "cgroup:<dest>[,ro|rw]";
+
+    fn parse(rest: &str) -> Result<MountEntry, ParseMountError> {
+        let (dest, opts) = rest.split_once(',').unwrap_or((rest, ""));
+        let dest = match dest.trim() {
+            "" => return Err(Self::err_syntax("destination path cannot be empty")),
+            d => PathBuf::from(d),
+        };
+
+        // Read-only unless asked, the sandbox rarely needs to create cgroups
+        let mode = match opts.trim() {
+            "" | "ro" => Mode::ReadOnly,
+            "rw" => Mode::ReadWrite,
+            opt => {
+                return Err(Self::err_option(format!(
+                    "unknown option '{opt}' (valid: ro, rw)"
+                )));
+            }
+        };
+
+        Ok(MountEntry::Cgroup { dest, mode })
+    }
+}
+
+struct ChmodMount;
+
+impl MountParser for ChmodMount {
+    const KIND: &'static str = "chmod";
+    const SYNTAX: &'static str = "chmod:<octal>:<path>";
+
+    fn parse(rest: &str) -> Result<MountEntry, ParseMountError> {
+        let (mode, path) = rest
+            .split_once(':')
+            .ok_or_else(|| Self::err_syntax("missing ':' between mode and path"))?;
+
+        let pair = format!("{} {}", mode.trim(), path.trim())
+            .parse::<ChmodPair>()
+            .map_err(Self::err_option)?;
+
+        let path = &pair.path;
+        if !path.is_absolute() {
+            return Err(Self::err_syntax("path must be absolute"));
+        }
+
+        // Symlinks are checked once the root is populated, '..' can be refused right away
+        if path.components().any(|c| c == Component::ParentDir) {
+            return Err(Self::err_syntax("path cannot contain '..'"));
+        }
+
+        Ok(MountEntry::Chmod(pair))
+    }
+}
+
 struct DevMount;
 
 impl MountParser for DevMount {
@@ -525,7 +1130,7 @@ struct FileMount;
 
 impl MountParser for FileMount {
     const KIND: &'static str = "file";
-    const SYNTAX: &'static str = "file:fd=<N>:<dest>,mode=<octal>";
+    const SYNTAX: &'static str = "file:fd=<N>:<dest>[,mode=<octal>][,ro][,sealed]";
 
     fn parse(rest: &str) -> Result<MountEntry, ParseMountError> {
         let (fd, rest) = rest
@@ -545,33 +1150,65 @@ impl MountParser for FileMount {
                 )),
             })?;
 
-        let (dest, mode) = match rest.split_once(',') {
-            None => (rest.trim(), None),
+        let (dest, opts) = match rest.split_once(',') {
+            None => (rest.trim(), ""),
             Some(("", _)) => return Err(Self::err_syntax("destination cannot be empty")),
-            Some((d, m)) => {
-                let mode = m
-                    .trim()
-                    .strip_prefix("mode=")
-                    .ok_or_else(|| Self::err_syntax("expected 'mode=<octal>'"))?
-                    .parse::<OctalPermissions>()
-                    .map_err(|_| {
-                        Self::err_syntax(format!(
-                            "invalid mode value '{}', expected octal",
-                            m.trim()
-                        ))
-                    })?;
-                (d.trim(), Some(mode))
-            }
+            Some((d, o)) => (d.trim(), o.trim()),
         };
 
+        let FileOpts {
+            mode,
+            read_only,
+            sealed,
+        } = FileOpts::parse(opts)?;
+
         Ok(MountEntry::File {
             dest: PathBuf::from(dest),
             fd,
             mode,
+            read_only,
+            sealed,
         })
     }
 }
 
+#[derive(Default)]
+struct FileOpts {
+    mode: Option<OctalPermissions>,
+    read_only: bool,
+    sealed: bool,
+}
+
+impl FileOpts {
+    fn parse(opts: &str) -> Result<Self, ParseMountError> {
+        let mut parsed = Self::default();
+
+        for opt in opts.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match opt {
+                "ro" => parsed.read_only = true,
+                "sealed" => parsed.sealed = true,
+                opt if let Some(mode) = opt.strip_prefix("mode=") => {
+                    if parsed.mode.is_some() {
+                        return Err(FileMount::err_option("duplicate 'mode' passed"));
+                    }
+                    parsed.mode = Some(mode.trim().parse::<OctalPermissions>().map_err(|_| {
+                        FileMount::err_option(format!(
+                            "invalid mode value '{mode}', expected octal"
+                        ))
+                    })?);
+                }
+                opt => {
+                    return Err(FileMount::err_option(format!(
+                        "unknown option '{opt}' (valid: mode=<octal>, ro, sealed)"
+                    )));
+                }
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
 struct MQueueMount;
 
 impl MountParser for MQueueMount {
@@ -592,10 +1229,80 @@ struct OverlayMount;
 
 impl MountParser for OverlayMount {
     const KIND: &'static str = "overlay";
-    const SYNTAX: &'static str = "overlay:<dest>,lowerdir=<path>[,upperdir=<path>,workdir=<path>]";
+    const SYNTAX: &'static str =
+        "overlay:<dest>,lowerdir=<path>[:<path>...][,upperdir=<path>,workdir=<path>]";
+
+    fn parse(rest: &str) -> Result<MountEntry, ParseMountError> {
+        let (dest, opts) = rest.split_once(',').unwrap_or((rest, ""));
+        let dest = match dest.trim() {
+            "" => return Err(Self::err_syntax("destination path cannot be empty")),
+            d => PathBuf::from(d),
+        };
+
+        let (mut lowerdir, mut upperdir, mut workdir) = (Vec::new(), None, None);
+
+        for opt in opts.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            match opt.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                Some((_, "")) => {
+                    return Err(Self::err_syntax(format!("empty path in '{opt}'")));
+                }
+                Some(("lowerdir", dirs)) if lowerdir.is_empty() => {
+                    // Leftmost lowerdir is the top-most layer, same as overlayfs
+                    for dir in dirs.split(':').map(str::trim) {
+                        if dir.is_empty() {
+                            return Err(Self::err_syntax("lowerdir path cannot be empty"));
+                        }
+                        lowerdir.push(PathBuf::from(dir));
+                    }
+                }
+                Some(("upperdir", dir)) if upperdir.is_none() => {
+                    upperdir = Some(PathBuf::from(dir))
+                }
+                Some(("workdir", dir)) if workdir.is_none() => workdir = Some(PathBuf::from(dir)),
+                Some((key @ ("lowerdir" | "upperdir" | "workdir"), _)) => {
+                    return Err(Self::err_option(format!("duplicate '{key}' passed")));
+                }
+                _ => {
+                    return Err(Self::err_option(format!(
+                        "unknown option '{opt}' (valid: lowerdir, upperdir, workdir)"
+                    )));
+                }
+            }
+        }
+
+        if lowerdir.is_empty() {
+            return Err(Self::err_syntax("at least one lowerdir is required"));
+        }
+
+        if upperdir.is_some() != workdir.is_some() {
+            return Err(Self::err_syntax(
+                "upperdir and workdir must be passed together",
+            ));
+        }
 
-    fn parse(_rest: &str) -> Result<MountEntry, ParseMountError> {
-        todo!()
+        Ok(MountEntry::Overlay {
+            dest,
+            lowerdir,
+            upperdir,
+            workdir,
+        })
+    }
+}
+
+struct DevptsMount;
+
+impl MountParser for DevptsMount {
+    const KIND: &'static str = "devpts";
+    const SYNTAX: &'static str = "devpts:<dest>";
+
+    fn parse(rest: &str) -> Result<MountEntry, ParseMountError> {
+        let rest = rest.trim();
+
+        Ok(MountEntry::Devpts {
+            dest: (!rest.is_empty())
+                .then(|| PathBuf::from(rest))
+                .ok_or_else(|| Self::err_syntax("destination path cannot be empty"))?,
+        })
     }
 }
 
@@ -616,6 +1323,46 @@ impl MountParser for ProcMount {
     }
 }
 
+struct RemountMount;
+
+impl MountParser for RemountMount {
+    const KIND: &'static str = "remount";
+    const SYNTAX: &'static str = "remount:<dest>,ro[,recursive]";
+
+    fn parse(rest: &str) -> Result<MountEntry, ParseMountError> {
+        let (dest, opts) = rest.split_once(',').unwrap_or((rest, ""));
+        let dest = match dest.trim() {
+            "" => return Err(Self::err_syntax("destination path cannot be empty")),
+            d => PathBuf::from(d),
+        };
+
+        let (mut read_only, mut recursive) = (false, false);
+        for opt in opts.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let flag = match opt {
+                "ro" => &mut read_only,
+                "recursive" => &mut recursive,
+                _ => {
+                    return Err(Self::err_option(format!(
+                        "unknown option '{opt}' (valid: ro, recursive)"
+                    )));
+                }
+            };
+
+            if *flag {
+                return Err(Self::err_option(format!("duplicate '{opt}' passed")));
+            }
+            *flag = true;
+        }
+
+        // Nothing else can be remounted yet, keep the flag explicit on the command line
+        if !read_only {
+            return Err(Self::err_syntax("'ro' is required"));
+        }
+
+        Ok(MountEntry::Remount { dest, recursive })
+    }
+}
+
 struct SymlinkMount;
 
 impl MountParser for SymlinkMount {
@@ -660,14 +1407,93 @@ impl SymlinkMount {
     }
 }
 
+struct SysfsMount;
+
+impl MountParser for SysfsMount {
+    const KIND: &'static str = "sysfs";
+    const SYNTAX: &'static str = "sysfs:<dest>";
+
+    fn parse(rest: &str) -> Result<MountEntry, ParseMountError> {
+        let rest = rest.trim();
+
+        Ok(MountEntry::Sysfs {
+            dest: (!rest.is_empty())
+                .then(|| PathBuf::from(rest))
+                .ok_or_else(|| Self::err_syntax("destination path cannot be empty"))?,
+        })
+    }
+}
+
 struct TmpfsMount;
 
 impl MountParser for TmpfsMount {
     const KIND: &'static str = "tmpfs";
-    const SYNTAX: &'static str = "tmpfs:<dest>[,size=<N>][,mode=<octal>]";
+    const SYNTAX: &'static str =
+        "tmpfs:<dest>[,size=<N>[K|M|G]][,mode=<octal>][,nr_inodes=<N>[K|M|G]][,uid=<N>][,gid=<N>]";
+
+    fn parse(rest: &str) -> Result<MountEntry, ParseMountError> {
+        let (dest, opts) = rest.split_once(',').unwrap_or((rest, ""));
+        let dest = match dest.trim() {
+            "" => return Err(Self::err_syntax("destination path cannot be empty")),
+            d => PathBuf::from(d),
+        };
+
+        let (mut size, mut permission, mut nr_inodes, mut uid, mut gid) =
+            (None, None, None, None, None);
+
+        for opt in opts.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+            let (key, value) = opt
+                .split_once('=')
+                .map(|(k, v)| (k.trim(), v.trim()))
+                .ok_or_else(|| {
+                    Self::err_option(format!("expected '<key>=<value>', got '{opt}'"))
+                })?;
 
-    fn parse(_rest: &str) -> Result<MountEntry, ParseMountError> {
-        todo!()
+            match key {
+                "size" if size.is_none() => {
+                    size = Some(value.parse::<Size>().map_err(Self::err_option)?);
+                }
+                "mode" if permission.is_none() => {
+                    permission = Some(value.parse::<OctalPermissions>().map_err(|_| {
+                        Self::err_option(format!("invalid mode value '{value}', expected octal"))
+                    })?);
+                }
+                "nr_inodes" if nr_inodes.is_none() => {
+                    nr_inodes = Some(value.parse::<Size>().map_err(Self::err_option)?.0);
+                }
+                "uid" if uid.is_none() => {
+                    uid = Some(
+                        value
+                            .parse::<u32>()
+                            .map_err(|_| Self::err_option(format!("invalid uid '{value}'")))?,
+                    );
+                }
+                "gid" if gid.is_none() => {
+                    gid = Some(
+                        value
+                            .parse::<u32>()
+                            .map_err(|_| Self::err_option(format!("invalid gid '{value}'")))?,
+                    );
+                }
+                "size" | "mode" | "nr_inodes" | "uid" | "gid" => {
+                    return Err(Self::err_option(format!("duplicate '{key}' passed")));
+                }
+                key => {
+                    return Err(Self::err_option(format!(
+                        "unknown option '{key}' (valid: size, mode, nr_inodes, uid, gid)"
+                    )));
+                }
+            }
+        }
+
+        Ok(MountEntry::Tmpfs {
+            dest,
+            size,
+            permission,
+            nr_inodes,
+            uid,
+            gid,
+        })
     }
 }
 
@@ -740,15 +1566,14 @@ impl std::str::FromStr for ChmodPair {
     type Err = String;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let parts: Vec<&str> = s.split_whitespace().collect();
-        match parts.as_slice() {
-            [octal_str, path] => {
-                let permissions = u32::from_str_radix(octal_str, 8)
-                    .map_err(|_| format!("Invalid octal permissions: '{}'", octal_str))?;
+        // Only the first whitespace separates, the path may contain more
+        match s.trim().split_once(char::is_whitespace) {
+            Some((octal_str, path)) if !path.trim().is_empty() => {
+                let permissions = octal_str.parse::<OctalPermissions>()?;
 
                 Ok(ChmodPair {
-                    permissions,
-                    path: PathBuf::from(path),
+                    permissions: *permissions,
+                    path: PathBuf::from(path.trim()),
                 })
             }
             _ => Err(format!("Expected 'OCTAL PATH', got: '{}'", s)),
@@ -855,3 +1680,258 @@ fn validate_fd_arg(input: &str) -> Result<()> {
     let raw_fd = input.parse::<i32>()?;
     is_fd_valid(raw_fd).map(|_| {})
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tmpfs_parse_options() {
+        let entry = "tmpfs:/tmp,size=64M,mode=1777,nr_inodes=4k,uid=1000,gid=100"
+            .parse::<MountEntry>()
+            .unwrap();
+
+        match entry {
+            MountEntry::Tmpfs {
+                dest,
+                size,
+                permission,
+                nr_inodes,
+                uid,
+                gid,
+            } => {
+                assert_eq!(dest, PathBuf::from("/tmp"));
+                assert_eq!(size, Some(Size(64 * 1024 * 1024)));
+                assert_eq!(permission.map(|p| *p), Some(0o1777));
+                assert_eq!(nr_inodes, Some(4096));
+                assert_eq!((uid, gid), (Some(1000), Some(100)));
+            }
+            entry => panic!("unexpected entry: {entry:?}"),
+        }
+    }
+
+    #[test]
+    fn test_tmpfs_parse_rejects_invalid() {
+        for arg in [
+            "tmpfs:",
+            "tmpfs:/tmp,size=lots",
+            "tmpfs:/tmp,mode=999",
+            "tmpfs:/tmp,size=1M,size=2M",
+            "tmpfs:/tmp,noexec=1",
+        ] {
+            assert!(arg.parse::<MountEntry>().is_err(), "accepted '{arg}'");
+        }
+    }
+
+    #[test]
+    fn test_overlay_parse_layers() {
+        let entry = "overlay:/opt,lowerdir=/a:/b".parse::<MountEntry>().unwrap();
+
+        match entry {
+            MountEntry::Overlay {
+                lowerdir,
+                upperdir: None,
+                workdir: None,
+                ..
+            } => assert_eq!(lowerdir, [PathBuf::from("/a"), PathBuf::from("/b")]),
+            entry => panic!("unexpected entry: {entry:?}"),
+        }
+
+        assert!(
+            "overlay:/opt,lowerdir=/a,upperdir=/u"
+                .parse::<MountEntry>()
+                .is_err()
+        );
+        assert!(
+            "overlay:/opt,upperdir=/u,workdir=/w"
+                .parse::<MountEntry>()
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_try_suffix_marks_optional() {
+        let spec = "bind-try:/lib64:/lib64,ro".parse::<MountSpec>().unwrap();
+        assert!(spec.optional);
+        assert!(matches!(spec.entry, MountEntry::Bind { .. }));
+
+        let spec = "bind:/lib64:/lib64,ro".parse::<MountSpec>().unwrap();
+        assert!(!spec.optional);
+
+        assert!(matches!(
+            "nope-try:/x".parse::<MountSpec>(),
+            Err(ParseMountError::UnknownKind { .. })
+        ));
+    }
+
+    #[test]
+    fn test_propagation_option() {
+        let spec = "bind:/srv:/srv,ro,propagation=slave"
+            .parse::<MountSpec>()
+            .unwrap();
+        assert!(matches!(spec.propagation, Some(Propagation::Slave)));
+        assert!(matches!(
+            spec.entry,
+            MountEntry::Bind {
+                mode: Mode::ReadOnly,
+                ..
+            }
+        ));
+
+        let spec = "proc:/proc,propagation=private"
+            .parse::<MountSpec>()
+            .unwrap();
+        assert!(matches!(spec.propagation, Some(Propagation::Private)));
+
+        assert!(
+            "bind:/srv:/srv,propagation=bogus"
+                .parse::<MountSpec>()
+                .is_err()
+        );
+        assert!(
+            "symlink:usr/bin:/bin,propagation=shared"
+                .parse::<MountSpec>()
+                .is_err()
+        );
+        assert!("dir:/data,propagation=shared".parse::<MountSpec>().is_err());
+    }
+
+    #[test]
+    fn test_generic_mount_attrs() {
+        let spec = "tmpfs:/scratch,size=1M,noexec,noatime"
+            .parse::<MountSpec>()
+            .unwrap();
+        assert!(spec.attrs.noexec && !spec.attrs.nosuid);
+        assert_eq!(spec.attrs.atime, Some(Atime::Noatime));
+        assert!(matches!(
+            spec.entry,
+            MountEntry::Tmpfs { size: Some(_), .. }
+        ));
+
+        let spec = "bind:/etc:/etc,ro,nosymfollow"
+            .parse::<MountSpec>()
+            .unwrap();
+        assert!(spec.attrs.nosymfollow);
+
+        assert!(
+            "proc:/proc,noatime,strictatime"
+                .parse::<MountSpec>()
+                .is_err()
+        );
+        assert!("proc:/proc,noexec,noexec".parse::<MountSpec>().is_err());
+        assert!("bind:/dev:/dev,dev,nodev".parse::<MountSpec>().is_err());
+        assert!("dir:/data,noexec".parse::<MountSpec>().is_err());
+    }
+
+    #[test]
+    fn test_writable_option() {
+        let spec = "tmpfs:/tmp,rw,size=1M".parse::<MountSpec>().unwrap();
+        assert!(spec.writable);
+
+        let spec = "bind:/data:/data,rw".parse::<MountSpec>().unwrap();
+        assert!(
+            spec.writable
+                && matches!(
+                    spec.entry,
+                    MountEntry::Bind {
+                        mode: Mode::ReadWrite,
+                        ..
+                    }
+                )
+        );
+
+        // Writable by default, but not marked
+        let spec = "bind:/data:/data".parse::<MountSpec>().unwrap();
+        assert!(!spec.writable);
+
+        assert!("bind:/data:/data,ro,rw".parse::<MountSpec>().is_err());
+        assert!("remount:/etc,ro,rw".parse::<MountSpec>().is_err());
+        assert!("dir:/data,rw".parse::<MountSpec>().is_err());
+    }
+
+    #[test]
+    fn test_remount_parse() {
+        let spec = "remount:/etc,ro".parse::<MountSpec>().unwrap();
+        assert!(matches!(
+            spec.entry,
+            MountEntry::Remount {
+                recursive: false,
+                ..
+            }
+        ));
+
+        let spec = "remount:/data,recursive,ro".parse::<MountSpec>().unwrap();
+        assert!(matches!(
+            spec.entry,
+            MountEntry::Remount {
+                recursive: true,
+                ..
+            }
+        ));
+
+        assert!("remount:/etc".parse::<MountSpec>().is_err());
+        assert!("remount:/etc,rw".parse::<MountSpec>().is_err());
+        assert!("remount:,ro".parse::<MountSpec>().is_err());
+    }
+
+    #[test]
+    fn test_idmap_option() {
+        let spec = "bind:/srv/cache:/cache,rw,idmap"
+            .parse::<MountSpec>()
+            .unwrap();
+        assert_eq!(spec.idmap, Some(IdMap::Sandbox));
+
+        let spec = "overlay:/src,lowerdir=/a:/b,idmap=/proc/42/ns/user"
+            .parse::<MountSpec>()
+            .unwrap();
+        assert_eq!(
+            spec.idmap,
+            Some(IdMap::Userns(PathBuf::from("/proc/42/ns/user")))
+        );
+
+        assert!("bind:/a:/b,idmap,idmap".parse::<MountSpec>().is_err());
+        assert!("bind:/a:/b,idmap=ns/user".parse::<MountSpec>().is_err());
+        assert!("bind:fd=3:/b,idmap".parse::<MountSpec>().is_err());
+        assert!("tmpfs:/tmp,idmap".parse::<MountSpec>().is_err());
+        assert!(
+            "bind:/a:/b,idmap,propagation=shared"
+                .parse::<MountSpec>()
+                .is_err()
+        );
+
+        assert_eq!(
+            common_parent(Path::new("/data/ovl/upper"), Path::new("/data/ovl/work")),
+            Path::new("/data/ovl")
+        );
+        assert!(
+            "overlay:/src,lowerdir=/a,upperdir=/data/upper,workdir=/srv/work,idmap"
+                .parse::<MountSpec>()
+                .is_err()
+        );
+        assert!(
+            "overlay:/src,lowerdir=/a,upperdir=/data/upper,workdir=/data/work,idmap"
+                .parse::<MountSpec>()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_chmod_parse() {
+        let spec = "chmod:0711:/run/app.sock".parse::<MountSpec>().unwrap();
+        assert!(matches!(
+            spec.entry,
+            MountEntry::Chmod(ChmodPair { permissions: 0o711, ref path }) if path == Path::new("/run/app.sock")
+        ));
+
+        assert!("chmod:999:/x".parse::<MountSpec>().is_err());
+        assert!("chmod:0755:relative".parse::<MountSpec>().is_err());
+        assert!("chmod:0755:/a/../../etc".parse::<MountSpec>().is_err());
+        assert!("chmod:0755:/x,noexec".parse::<MountSpec>().is_err());
+
+        let pair = "0640 /srv/my data".parse::<ChmodPair>().unwrap();
+        assert_eq!(pair.permissions, 0o640);
+        assert_eq!(pair.path, Path::new("/srv/my data"));
+        assert!("0640".parse::<ChmodPair>().is_err());
+        assert!("17777 /x".parse::<ChmodPair>().is_err());
+    }
+}
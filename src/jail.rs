@@ -5,22 +5,84 @@ use crate::{
     jailer::HostResource,
     mount::{
         MountContext,
-        pivot::{PivotContext, Uninitialized},
+        audit::MountAudit,
+        bind::SharedSources,
+        info::MountInfo,
+        pivot::{PivotContext, PivotError, Staging, Uninitialized},
+        tree::MountTree,
     },
     utils::{IdentityMap, SelfWriter},
 };
-use anyhow::Result;
-use nix::unistd::{Gid, Uid, execvp};
-use std::{ffi::CString, marker::PhantomData};
+use anyhow::{Context, Result, anyhow};
+use nix::{
+    fcntl::{OFlag, open},
+    mount::{MntFlags, umount2},
+    sys::stat::Mode,
+    unistd::{Gid, Uid, UnlinkatFlags, execvp, mkdtemp, unlinkat},
+};
+use std::{
+    ffi::{CString, OsString},
+    marker::PhantomData,
+    os::fd::OwnedFd,
+    path::{Path, PathBuf},
+};
 
 mod sealed {
     pub trait Sealed {}
 }
 
-const BASE_PATH: &str = "/tmp";
 const NEW_ROOT: &str = "newroot";
 const OLD_ROOT: &str = "oldroot";
 
+/// Tried in order when no '--staging-dir' is passed, the first one that can be staged in wins.
+fn default_staging_bases() -> impl Iterator<Item = PathBuf> {
+    std::iter::once(PathBuf::from("/tmp"))
+        .chain(std::env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from))
+}
+
+/// Mounts the staging tmpfs over `dir` and pivots into it.
+fn pivot_to_staging(dir: &Path) -> Result<PivotContext<Staging>, PivotError> {
+    PivotContext::<Uninitialized>::new(dir, NEW_ROOT, OLD_ROOT)?
+        .enslave_and_mount()?
+        .bind_new_root()?
+        .first_pivot()
+}
+
+/// Private per-invocation directory the staging tmpfs is mounted over.
+///
+/// Only the sandbox's mount namespace sees the tmpfs, but the directory itself is on the
+/// host, so it's removed through `parent` once the sandbox can't reach it by path anymore.
+struct StagingDir {
+    parent: OwnedFd,
+    name: OsString,
+    path: PathBuf,
+}
+
+impl StagingDir {
+    fn create(base: &Path) -> nix::Result<Self> {
+        let parent = open(
+            base,
+            OFlag::O_PATH | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        )?;
+        let path = mkdtemp(&base.join("enclosure-XXXXXX"))?;
+        let name = path
+            .file_name()
+            .expect("mkdtemp() fills in a name")
+            .to_owned();
+
+        Ok(Self { parent, name, path })
+    }
+
+    fn remove(&self) -> nix::Result<()> {
+        unlinkat(
+            &self.parent,
+            self.name.as_os_str(),
+            UnlinkatFlags::RemoveDir,
+        )
+    }
+}
+
 impl sealed::Sealed for AwaitingPrivileges {}
 impl sealed::Sealed for Privileged {}
 impl sealed::Sealed for Isolated {}
@@ -93,34 +155,114 @@ impl<'resource> Jail<'resource, AwaitingPrivileges> {
 
 impl<'resource> Jail<'resource, Privileged> {
     pub fn isolate(self) -> Result<Jail<'resource, Isolated>> {
-        PivotContext::<Uninitialized>::new(BASE_PATH, NEW_ROOT, OLD_ROOT)?
-            .enslave_and_mount()?
-            .bind_new_root()?
-            .first_pivot()?
+        // Must happen before '/' is made a slave, clones taken afterwards can't be peers of the host.
+        let shared = SharedSources::capture(self.config.shared_binds())?;
+
+        let (staging, private) = self.enter_staging()?;
+
+        staging
             .stage(
                 |oldroot_abs /* '/oldroot' */, newroot_abs /* '/newroot' */| {
-                    MountContext::new(
+                    let mut mounts = MountContext::new(
                         &self.config.mount,
+                        &self.config.mount_opts,
                         &self.config.namespace,
                         oldroot_abs,
                         newroot_abs,
-                    )
-                    .apply()
+                    )?
+                    .with_shared(&shared);
+
+                    if let Some(idmapped) = self.resource.idmapped() {
+                        mounts = mounts.with_idmapped(idmapped);
+                    }
+                    mounts.apply()?;
+
+                    if self.config.mount_opts.ro_root {
+                        mounts.seal_root(self.resource.proc_fd())?;
+                    }
+                    Ok(())
                 },
             )?
             .detach_old_root()?
             .second_pivot()?
             .detach_staging()?;
 
+        if let Some(dir) = private
+            && let Err(e) = dir.remove()
+        {
+            eprintln!(
+                "[CHILD]: Failed to remove staging directory {}: {e}",
+                dir.path.display()
+            );
+        }
+
         Ok(Jail {
             config: self.config,
             resource: self.resource,
             _state: PhantomData,
         })
     }
+
+    /// A '--staging-dir' is used as is, otherwise each default gets a private directory.
+    fn enter_staging(&self) -> Result<(PivotContext<Staging>, Option<StagingDir>)> {
+        if let Some(dir) = &self.config.mount_opts.staging_dir {
+            let staging = pivot_to_staging(dir)
+                .with_context(|| format!("Failed to stage in {}", dir.display()))?;
+            return Ok((staging, None));
+        }
+
+        let mut errors = Vec::new();
+        for base in default_staging_bases() {
+            let dir = match StagingDir::create(&base) {
+                Ok(dir) => dir,
+                Err(e) => {
+                    errors.push(format!("{}: {e}", base.display()));
+                    continue;
+                }
+            };
+
+            match pivot_to_staging(&dir.path) {
+                Ok(staging) => return Ok((staging, Some(dir))),
+                Err(e) => {
+                    errors.push(format!("{}: {:#}", dir.path.display(), anyhow!(e)));
+
+                    // Best effort, the tmpfs and the directory may not be there yet
+                    let _ = umount2(&dir.path, MntFlags::MNT_DETACH);
+                    let _ = dir.remove();
+                }
+            }
+        }
+
+        Err(anyhow!(
+            "No staging directory could be used, pass --staging-dir:\n{}",
+            errors.join("\n")
+        ))
+    }
 }
 
 impl<'resource> Jail<'resource, Isolated> {
+    /// Fails closed if the mounts the sandbox can see don't match the mount plan.
+    pub fn audit(self) -> Result<Self> {
+        let info = MountInfo::try_from(self.resource.proc_fd())?;
+        let tree = MountTree::try_from(info).context("Failed to read the sandbox's mounts")?;
+
+        let report = MountAudit::new(
+            &self.config.mount,
+            &self.config.namespace,
+            NEW_ROOT,
+            OLD_ROOT,
+        )
+        .ro_root(self.config.mount_opts.ro_root)
+        .check(&tree);
+        if !report.is_empty() {
+            return Err(anyhow!(
+                "Sandbox mounts don't match the mount plan:\n{report}\nMounted:\n{tree}"
+            ));
+        }
+
+        Ok(self)
+    }
+
     pub fn restrict(self) -> Result<Jail<'resource, Restricted>> {
         if self.config.namespace.unshare_user {
             CapabilityManager::drop_all_bounding_capabilities()?;
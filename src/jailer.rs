@@ -5,6 +5,7 @@ use crate::{
         reporter::ErrorReporter,
     },
     jail::{self, Jail},
+    mount::bind::IdmappedSources,
     utils::GuardedStack,
 };
 use anyhow::{Context, Result, anyhow};
@@ -62,6 +63,8 @@ impl ChildContext<'_> {
             .context("Privilege setup phase failed")?
             .isolate()
             .context("Isolation phase failed")?
+            .audit()
+            .context("Mount audit failed")?
             .restrict()
             .context("Restriction phase failed")?
             .execute()
@@ -238,21 +241,38 @@ impl ExitHandler {
 
 pub struct HostResource<'resource> {
     proc_fd: BorrowedFd<'resource>,
+    idmapped: Option<&'resource IdmappedSources>,
 }
 
 impl<'resource> HostResource<'resource> {
     pub fn new(proc_fd: BorrowedFd<'resource>) -> Self {
-        Self { proc_fd }
+        Self {
+            proc_fd,
+            idmapped: None,
+        }
+    }
+
+    pub fn with_idmapped(mut self, idmapped: &'resource IdmappedSources) -> Self {
+        self.idmapped = Some(idmapped);
+        self
     }
 
     #[inline]
     pub fn proc_fd(&self) -> BorrowedFd<'resource> {
         self.proc_fd
     }
+
+    #[inline]
+    pub fn idmapped(&self) -> Option<&'resource IdmappedSources> {
+        self.idmapped
+    }
 }
 
 impl Clone for HostResource<'_> {
     fn clone(&self) -> Self {
-        Self::new(self.proc_fd)
+        Self {
+            proc_fd: self.proc_fd,
+            idmapped: self.idmapped,
+        }
     }
 }
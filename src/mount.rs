@@ -1,120 +1,865 @@
+pub mod attr;
+pub mod audit;
 pub mod bind;
-mod info;
+pub mod fsmount;
+pub mod info;
 pub mod pivot;
+pub mod plan;
+pub mod root;
+pub mod tree;
 
 use crate::{
-    config::{MountEntry, NamespaceOptions},
+    config::{
+        self, Atime, ChmodPair, Mode, MountAttrs, MountEntry, MountOptions, MountSource, MountSpec,
+        NamespaceOptions, OctalPermissions,
+    },
     utils,
 };
 use anyhow::{Context, Result, anyhow};
-use nix::mount::{MsFlags, mount};
+use attr::{MountAttr, SetAttr};
+use bind::{BindMount, IdmappedSources, SharedSources};
+use fsmount::FsMount;
+use info::{MountFlags, MountInfo, MountLine};
+use nix::{
+    errno::Errno,
+    fcntl::{FcntlArg, OFlag, SealFlag, fcntl},
+    sys::stat::{self, SFlag, fstat},
+};
+use root::RootFd;
 use std::{
-    io,
-    os::unix::fs::symlink,
+    cell::Cell,
+    collections::HashSet,
+    fs::{File, Permissions},
+    io::{ErrorKind, Read, Seek, SeekFrom, Write},
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+    os::unix::fs::PermissionsExt,
     path::{Path, PathBuf},
 };
+use tree::MountTree;
 
 #[derive(Debug)]
 pub struct MountContext<'ctx> {
-    mount: &'ctx [MountEntry],
+    mount: &'ctx [MountSpec],
+    options: &'ctx MountOptions,
     namespace: &'ctx NamespaceOptions,
     oldroot: &'ctx Path,
     newroot: &'ctx Path,
+    root: RootFd,
+    shared: Option<&'ctx SharedSources>,
+    idmapped: Option<&'ctx IdmappedSources>,
+    overlays: Cell<usize>,
 }
 
 impl<'ctx> MountContext<'ctx> {
     pub fn new<P1, P2>(
-        mount: &'ctx [MountEntry],
+        mount: &'ctx [MountSpec],
+        options: &'ctx MountOptions,
         namespace: &'ctx NamespaceOptions,
         oldroot: &'ctx P1,
         newroot: &'ctx P2,
-    ) -> Self
+    ) -> Result<Self>
     where
         P1: AsRef<Path> + ?Sized,
         P2: AsRef<Path> + ?Sized,
     {
-        MountContext {
+        let newroot = newroot.as_ref();
+        let root = RootFd::open(newroot)
+            .with_context(|| format!("Failed to open new root {}", newroot.display()))?;
+
+        Ok(MountContext {
             mount,
+            options,
             namespace,
             oldroot: oldroot.as_ref(),
-            newroot: newroot.as_ref(),
-        }
+            newroot,
+            root,
+            shared: None,
+            idmapped: None,
+            overlays: Cell::new(0),
+        })
+    }
+
+    /// Trees captured before '/' was made a slave, used for `propagation=shared` binds.
+    pub fn with_shared(mut self, shared: &'ctx SharedSources) -> Self {
+        self.shared = Some(shared);
+        self
+    }
+
+    /// Trees the parent cloned and ID-mapped, used for entries w/ `idmap`.
+    pub fn with_idmapped(mut self, idmapped: &'ctx IdmappedSources) -> Self {
+        self.idmapped = Some(idmapped);
+        self
     }
 }
 
 impl<'ctx> MountContext<'ctx> {
     pub fn apply(&self) -> Result<()> {
-        for mnt in self.mount {
-            self.apply_one(mnt)
-                .with_context(|| format!("Failed to apply mount entry: {mnt:?}"))?;
+        // Already ordered by the parent's `MountPlan`, the index keys shared/ID-mapped trees
+        let is_remount = |spec: &MountSpec| matches!(spec.entry, MountEntry::Remount { .. });
+        let specs = self.mount.iter().enumerate();
+
+        for (idx, spec) in specs.clone().filter(|(_, spec)| !is_remount(spec)) {
+            self.apply_spec(idx, spec)?;
+        }
+
+        if self.options.merged_usr {
+            self.apply_usr_links()
+                .context("Failed to replicate merged-/usr symlinks")?;
+        }
+
+        for (idx, spec) in specs.filter(|(_, spec)| is_remount(spec)) {
+            self.apply_spec(idx, spec)?;
+        }
+        Ok(())
+    }
+
+    /// Seals the new root and every mount in it read-only (`--ro-root`), runs after `apply()`.
+    ///
+    /// Entries passed w/ `rw` are left alone, along w/ whatever they brought that wasn't
+    /// declared, e.g. the host's submounts of a bind or the nodes of a '/dev'.
+    pub fn seal_root(&self, proc: BorrowedFd<'_>) -> Result<()> {
+        let info = MountInfo::try_from(proc)?;
+        let tree = MountTree::try_from(info).context("Failed to read the mount table")?;
+        let writable = writable_mounts(&tree, self.newroot, self.mount).ok_or_else(|| {
+            anyhow!(
+                "New root {} isn't a mountpoint, can't seal it",
+                self.newroot.display()
+            )
+        })?;
+
+        // A single recursive pass also reaches overmounted mounts, which can't be opened by path
+        SetAttr::new()
+            .set(MountAttr::RDONLY)
+            .recursive(true)
+            .apply_fd(self.root.open_path(Path::new("/"))?.as_fd())
+            .with_context(|| format!("Failed to seal {} read-only", self.newroot.display()))?;
+
+        for mount in writable {
+            let dest = dest_in(self.newroot, mount);
+            SetAttr::new()
+                .clear(MountAttr::RDONLY)
+                .apply_fd(self.root.open_path(&dest)?.as_fd())
+                .with_context(|| format!("Failed to keep {} writable", dest.display()))?;
+        }
+        Ok(())
+    }
+
+    /// Copies the host's top-level links into '/usr', but only if '/usr' got mounted and
+    /// the entry wasn't created explicitly.
+    fn apply_usr_links(&self) -> Result<()> {
+        const USR_LINKS: [&str; 4] = ["bin", "sbin", "lib", "lib64"];
+
+        let usr = Path::new("/usr");
+        let usr_mounted = self
+            .mount
+            .iter()
+            .any(|spec| spec.entry.creates_mount() && spec.entry.dest() == usr);
+        if !usr_mounted || self.open_dir(usr).is_err() {
+            return Ok(());
+        }
+
+        for name in USR_LINKS {
+            let Ok(target) = std::fs::read_link(self.oldroot.join(name)) else {
+                continue;
+            };
+
+            let into_usr = target
+                .strip_prefix("/")
+                .unwrap_or(&target)
+                .starts_with("usr");
+            let link = Path::new("/").join(name);
+
+            if into_usr && self.root.lstat(&link) == Err(Errno::ENOENT) {
+                self.root.symlink(&target, &link).with_context(|| {
+                    format!("Failed to link {} -> {}", link.display(), target.display())
+                })?;
+            }
         }
         Ok(())
     }
 
-    fn apply_one(&self, mnt: &MountEntry) -> Result<()> {
+    fn apply_spec(&self, idx: usize, spec: &MountSpec) -> Result<()> {
+        let MountSpec {
+            entry,
+            optional,
+            propagation,
+            attrs,
+            ..
+        } = spec;
+
+        if *optional && let Some(source) = self.missing_source(entry)? {
+            eprintln!("[CHILD]: Skipping optional mount, source '{source}' is missing");
+            return Ok(());
+        }
+
+        let tree = match entry {
+            MountEntry::Bind {
+                src: MountSource::Path { target, .. },
+                ..
+            } => self.idmapped_tree(idx, target),
+            _ => None,
+        }
+        .or_else(|| self.shared.and_then(|shared| shared.get(idx)));
+
+        self.apply_one(idx, entry, tree)
+            .with_context(|| format!("Failed to apply mount entry: {entry:?}"))?;
+
+        if !attrs.is_empty() {
+            let dest = entry.dest();
+            mount_attrs(attrs)
+                .apply_fd(self.root.open_path(dest)?.as_fd())
+                .with_context(|| format!("Failed to set mount options on {}", dest.display()))?;
+        }
+
+        if let Some(propagation) = propagation {
+            let dest = entry.dest();
+            SetAttr::new()
+                .propagation(propagation.flags())
+                .recursive(true)
+                .apply_fd(self.root.open_path(dest)?.as_fd())
+                .with_context(|| {
+                    format!(
+                        "Failed to set propagation {propagation:?} on {}",
+                        dest.display()
+                    )
+                })?;
+        }
+        Ok(())
+    }
+
+    fn idmapped_tree(&self, idx: usize, src: &Path) -> Option<BorrowedFd<'ctx>> {
+        self.idmapped.and_then(|idmapped| idmapped.get(idx, src))
+    }
+
+    /// Returns the first host-side source of `mnt` that doesn't exist, if any.
+    ///
+    /// Only a missing source counts, one that can't be checked fails the entry.
+    fn missing_source(&self, mnt: &MountEntry) -> Result<Option<String>> {
+        let missing_path = |path: &Path| match std::fs::symlink_metadata(self.rebase_old(path)) {
+            Ok(_) => Ok(None),
+            Err(e) if matches!(e.kind(), ErrorKind::NotFound | ErrorKind::NotADirectory) => {
+                Ok(Some(path.display().to_string()))
+            }
+            Err(e) => Err(e).with_context(|| format!("Failed to stat source {}", path.display())),
+        };
+        let missing_fd = |fd: i32| {
+            // SAFETY: only used for the fcntl() below, the fd isn't owned or kept
+            let borrowed = unsafe { BorrowedFd::borrow_raw(fd) };
+            match fcntl(borrowed, FcntlArg::F_GETFD) {
+                Ok(_) => Ok(None),
+                Err(Errno::EBADF) => Ok(Some(format!("fd={fd}"))),
+                Err(e) => Err(e).with_context(|| format!("Failed to check source fd={fd}")),
+            }
+        };
+
         match mnt {
-            MountEntry::Dir { path, mode } => match mode {
-                Some(mode) => utils::ensure_dir_with_mode(path, **mode),
-                None => utils::ensure_dir(path),
-            },
+            MountEntry::Bind {
+                src: MountSource::Path { target, .. },
+                ..
+            } => missing_path(target),
+            MountEntry::Bind {
+                src: MountSource::Fd(fd),
+                ..
+            }
+            | MountEntry::File { fd, .. } => missing_fd(*fd),
+            MountEntry::Overlay {
+                lowerdir,
+                upperdir,
+                workdir,
+                ..
+            } => {
+                for dir in lowerdir.iter().chain(upperdir).chain(workdir) {
+                    if let Some(missing) = missing_path(dir)? {
+                        return Ok(Some(missing));
+                    }
+                }
+                Ok(None)
+            }
+            MountEntry::Proc { .. } if !self.namespace.unshare_pid => {
+                missing_path(Path::new("/proc"))
+            }
+            MountEntry::Sysfs { .. } if !self.namespace.unshare_net => {
+                missing_path(Path::new("/sys"))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    fn apply_one(&self, idx: usize, mnt: &MountEntry, tree: Option<BorrowedFd<'_>>) -> Result<()> {
+        match mnt {
+            MountEntry::Bind { src, dest, mode } => self.apply_bind(src, dest, mode, tree),
+            MountEntry::Cgroup { dest, mode } => self.apply_cgroup(dest, mode),
+            MountEntry::Chmod(ChmodPair { permissions, path }) => {
+                self.apply_chmod(path, *permissions)
+            }
+            MountEntry::Dev { dest } => self.apply_dev(dest),
+            MountEntry::Devpts { dest } => self.apply_devpts(dest),
+            MountEntry::Dir { path, mode } => {
+                let mode = mode.map_or(0o755, |mode| *mode);
+                self.ensure_dir_with_mode(path, mode).map(drop)
+            }
+            MountEntry::File {
+                fd,
+                dest,
+                mode,
+                read_only,
+                sealed,
+            } => self.apply_file(*fd, dest, *mode, *read_only, *sealed),
             MountEntry::Mqueue { dest } => {
-                utils::ensure_dir(dest)?;
-                mount::<str, Path, str, str>(
-                    Some("mqueue"),
-                    &dest,
-                    Some("mqueue"),
-                    MsFlags::empty(),
-                    None,
-                )
-                .with_context(|| format!("Failed to mount mqueue at {}", dest.display()))?;
+                let target = self.ensure_dir(dest)?;
+                FsMount::new("mqueue")
+                    .attrs(MountAttr::NOSUID | MountAttr::NODEV)
+                    .mount_at(target.as_fd())
+                    .with_context(|| format!("Failed to mount mqueue at {}", dest.display()))?;
                 Ok(())
             }
+            MountEntry::Overlay {
+                dest,
+                lowerdir,
+                upperdir,
+                workdir,
+            } => self.apply_overlay(
+                idx,
+                dest,
+                lowerdir,
+                upperdir.as_deref().zip(workdir.as_deref()),
+            ),
             MountEntry::Proc { dest } => self.apply_proc(dest),
+            MountEntry::Remount { dest, recursive } => self.apply_remount(dest, *recursive),
+            MountEntry::Sysfs { dest } => self.apply_sysfs(dest),
+            MountEntry::Tmpfs {
+                dest,
+                size,
+                permission,
+                nr_inodes,
+                uid,
+                gid,
+            } => {
+                let mode = format!("{:o}", permission.map_or(0o755, |p| *p));
+                let optional = [
+                    ("size", size.map(|size| size.0.to_string())),
+                    ("nr_inodes", nr_inodes.map(|n| n.to_string())),
+                    ("uid", uid.map(|uid| uid.to_string())),
+                    ("gid", gid.map(|gid| gid.to_string())),
+                ];
+
+                let tmpfs = optional
+                    .into_iter()
+                    .filter_map(|(key, value)| Some((key, value?)))
+                    .fold(
+                        FsMount::new("tmpfs").option("mode", mode),
+                        |tmpfs, (key, value)| tmpfs.option(key, value),
+                    );
+
+                self.apply_tmpfs(dest, tmpfs)
+            }
             MountEntry::Symlink { target, link } => self.apply_symlink(target, link),
-            _ => todo!("Mount entry type not implemented: {mnt:?}"),
         }
     }
 
+    /// `tree` is the source captured up front, if the bind should stay a peer of the host.
+    fn apply_bind(
+        &self,
+        src: &MountSource,
+        dest: &Path,
+        mode: &Mode,
+        tree: Option<BorrowedFd<'_>>,
+    ) -> Result<()> {
+        let read_only = matches!(mode, Mode::ReadOnly);
+
+        match src {
+            MountSource::Path {
+                target: src,
+                mount_dev,
+            } => {
+                let source = self.rebase_old(src);
+                let target = self.ensure_mountpoint(source.is_dir(), dest)?;
+
+                match tree {
+                    Some(tree) => BindMount::from_tree(tree, target.as_fd()),
+                    None => BindMount::new(&source, target.as_fd()),
+                }
+                .read_only(read_only)
+                .allow_dev(*mount_dev)
+                .mount()
+                .with_context(|| {
+                    format!("Failed to bind {} to {}", source.display(), dest.display())
+                })
+            }
+            MountSource::Fd(fd) => utils::with_raw_fd(*fd, |borrowed| {
+                let is_dir = SFlag::from_bits_truncate(fstat(borrowed)?.st_mode) & SFlag::S_IFMT
+                    == SFlag::S_IFDIR;
+                let target = self.ensure_mountpoint(is_dir, dest)?;
+
+                BindMount::from_fd(borrowed, target.as_fd(), self.oldroot)
+                    .read_only(read_only)
+                    .mount()
+                    .with_context(|| format!("Failed to bind fd={fd:?} to {}", dest.display()))
+            }),
+        }
+    }
+
+    /// Creates `dest` as a directory or an empty regular file, matching the bind source.
+    fn ensure_mountpoint(&self, is_dir: bool, dest: &Path) -> Result<OwnedFd> {
+        match is_dir {
+            true => self.ensure_dir(dest),
+            false => self
+                .root
+                .ensure_file(dest, 0o444)
+                .with_context(|| format!("Failed to create mountpoint {}", dest.display())),
+        }
+    }
+
+    /// Symlinks are followed, but only as far as the new root.
+    fn apply_chmod(&self, path: &Path, permissions: u32) -> Result<()> {
+        // '/proc' isn't mounted in the staging root, the host's is still reachable
+        self.root
+            .chmod(path, permissions, &self.oldroot.join("proc"))
+            .map_err(|e| match e {
+                Errno::EOPNOTSUPP => anyhow!(
+                    "sockets, FIFOs and devices need fchmodat2() (Linux 6.6) or a host /proc"
+                ),
+                e => e.into(),
+            })
+            .with_context(|| format!("Failed to chmod {}", path.display()))
+    }
+
+    /// cgroup2 rooted at the sandbox's own cgroup, only possible w/ a CGROUP-NS.
+    fn apply_cgroup(&self, dest: &Path, mode: &Mode) -> Result<()> {
+        // '/proc' isn't mounted in the staging root, the host's is still reachable
+        if !utils::is_cgroups_supported(&self.oldroot.join("proc")) {
+            return Err(anyhow!(
+                "cgroup mounts require kernel support for cgroup namespaces"
+            ));
+        }
+
+        if !self.namespace.unshare_cgroup {
+            return Err(anyhow!(
+                "cgroup mounts require a cgroup namespace (pass --unshare-cgroup)"
+            ));
+        }
+
+        let target = self.ensure_dir(dest)?;
+
+        let mut attrs = MountAttr::NOSUID | MountAttr::NODEV | MountAttr::NOEXEC;
+        if matches!(mode, Mode::ReadOnly) {
+            attrs |= MountAttr::RDONLY;
+        }
+
+        FsMount::new("cgroup2")
+            .attrs(attrs)
+            .mount_at(target.as_fd())
+            .with_context(|| format!("Failed to mount cgroup2 at {}", dest.display()))?;
+
+        Ok(())
+    }
+
+    /// Minimal '/dev' like bubblewrap's '--dev', the host's devices are bind-mounted in.
+    fn apply_dev(&self, dest: &Path) -> Result<()> {
+        const DEVICES: [&str; 6] = ["null", "zero", "full", "random", "urandom", "tty"];
+        const SYMLINKS: [(&str, &str); 5] = [
+            ("/proc/self/fd", "fd"),
+            ("/proc/self/fd/0", "stdin"),
+            ("/proc/self/fd/1", "stdout"),
+            ("/proc/self/fd/2", "stderr"),
+            ("/proc/kcore", "core"),
+        ];
+
+        self.apply_tmpfs(dest, FsMount::new("tmpfs").option("mode", "0755"))?;
+
+        for device in DEVICES {
+            let (source, node) = (
+                self.rebase_old(Path::new("/dev")).join(device),
+                dest.join(device),
+            );
+            let target = self.ensure_mountpoint(false, &node)?;
+
+            BindMount::new(&source, target.as_fd())
+                .allow_dev(true)
+                .mount()
+                .with_context(|| format!("Failed to bind device {}", source.display()))?;
+        }
+
+        self.apply_devpts(&dest.join("pts"))?;
+
+        self.apply_tmpfs(
+            &dest.join("shm"),
+            FsMount::new("tmpfs").option("mode", "1777"),
+        )?;
+
+        for (link_target, link) in SYMLINKS {
+            self.apply_symlink(Path::new(link_target), &dest.join(link))?;
+        }
+
+        Ok(())
+    }
+
+    /// Copies the contents of `fd` (pipe, memfd, regular file) into a new file at `dest`.
+    fn apply_file(
+        &self,
+        fd: i32,
+        dest: &Path,
+        mode: Option<OctalPermissions>,
+        read_only: bool,
+        sealed: bool,
+    ) -> Result<()> {
+        let mut contents = Vec::new();
+
+        utils::with_raw_fd(fd, |borrowed| {
+            if sealed {
+                let seals = fcntl(borrowed, FcntlArg::F_GET_SEALS)
+                    .map(SealFlag::from_bits_truncate)
+                    .context("fd does not support seals")?;
+
+                let required =
+                    SealFlag::F_SEAL_WRITE | SealFlag::F_SEAL_SHRINK | SealFlag::F_SEAL_GROW;
+                if !seals.contains(required) {
+                    return Err(anyhow!(
+                        "fd is not sealed against writes (seals: {seals:?})"
+                    ));
+                }
+            }
+
+            let mut file = File::from(borrowed.try_clone_to_owned()?);
+
+            // Seekable sources are read from the start, the writer usually leaves the offset at EOF.
+            // Pipes can't seek and are read from wherever they are.
+            let _ = file.seek(SeekFrom::Start(0));
+            file.read_to_end(&mut contents)?;
+
+            Ok(())
+        })
+        .with_context(|| format!("Failed to read contents of fd={fd}"))?;
+
+        if let Some(parent) = dest.parent() {
+            self.ensure_dir(parent)?;
+        }
+
+        let mut file = self
+            .root
+            .resolve(
+                dest,
+                OFlag::O_WRONLY | OFlag::O_CREAT | OFlag::O_TRUNC,
+                stat::Mode::from_bits_truncate(0o666),
+            )
+            .map(File::from)
+            .with_context(|| format!("Failed to create {}", dest.display()))?;
+        file.write_all(&contents)?;
+
+        if let Some(mode) = mode {
+            file.set_permissions(Permissions::from_mode(*mode))?;
+        }
+
+        if read_only {
+            let target = self.root.open_path(dest)?;
+            BindMount::from_fd(target.as_fd(), target.as_fd(), self.oldroot)
+                .read_only(true)
+                .mount()
+                .with_context(|| format!("Failed to make {} read-only", dest.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Private devpts instance, '<dest>/../ptmx' is pointed at it so new ptys come from here.
+    fn apply_devpts(&self, dest: &Path) -> Result<()> {
+        let target = self.ensure_dir(dest)?;
+
+        FsMount::new("devpts")
+            .flag("newinstance")
+            .option("ptmxmode", "0666")
+            .option("mode", "620")
+            .attrs(MountAttr::NOSUID | MountAttr::NOEXEC)
+            .mount_at(target.as_fd())
+            .with_context(|| format!("Failed to mount devpts at {}", dest.display()))?;
+
+        let (Some(parent), Some(name)) = (dest.parent(), dest.file_name()) else {
+            return Ok(());
+        };
+
+        let ptmx = parent.join("ptmx");
+        let link_target = Path::new(name).join("ptmx");
+
+        match self.root.lstat(&ptmx) {
+            Ok(stat)
+                if SFlag::from_bits_truncate(stat.st_mode) & SFlag::S_IFMT == SFlag::S_IFLNK =>
+            {
+                self.root
+                    .unlink(&ptmx)
+                    .with_context(|| format!("Failed to remove stale {}", ptmx.display()))?;
+                self.apply_symlink(&link_target, &ptmx)
+            }
+            // e.g. a bind-mounted host ptmx, can't be unlinked so cover it instead
+            Ok(_) => {
+                let source = self.root.open_path(&dest.join("ptmx"))?;
+                let target = self.root.open_path(&ptmx)?;
+                BindMount::from_fd(source.as_fd(), target.as_fd(), self.oldroot)
+                    .allow_dev(true)
+                    .mount()
+                    .with_context(|| format!("Failed to refresh {}", ptmx.display()))
+            }
+            Err(Errno::ENOENT) => self.apply_symlink(&link_target, &ptmx),
+            Err(e) => Err(e).with_context(|| format!("Failed to stat {}", ptmx.display())),
+        }
+    }
+
+    /// Scratch tmpfs, always `nosuid,nodev`.
+    fn apply_tmpfs(&self, dest: &Path, tmpfs: FsMount<'_>) -> Result<()> {
+        let target = self.ensure_dir(dest)?;
+
+        tmpfs
+            .attrs(MountAttr::NOSUID | MountAttr::NODEV)
+            .mount_at(target.as_fd())
+            .with_context(|| format!("Failed to mount tmpfs at {}", dest.display()))?;
+
+        Ok(())
+    }
+
+    fn apply_overlay(
+        &self,
+        idx: usize,
+        dest: &Path,
+        lowerdir: &[PathBuf],
+        upper_work: Option<(&Path, &Path)>,
+    ) -> Result<()> {
+        let target = self.ensure_dir(dest)?;
+
+        let (upper, work) = match upper_work {
+            Some((upper, work)) => {
+                let parent = config::common_parent(upper, work);
+
+                match self.idmapped_tree(idx, &parent) {
+                    Some(tree) => {
+                        let base = self.attach_layer(tree)?;
+                        let under =
+                            |dir: &Path| base.join(dir.strip_prefix(&parent).unwrap_or(dir));
+                        (under(upper), under(work))
+                    }
+                    None => (self.rebase_old(upper), self.rebase_old(work)),
+                }
+            }
+            None => self.overlay_scratch()?,
+        };
+
+        let lower = lowerdir
+            .iter()
+            .map(|dir| {
+                self.overlay_layer(idx, dir)
+                    .map(|dir| escape_overlay_path(&dir))
+            })
+            .collect::<Result<Vec<_>>>()?
+            .join(":");
+
+        let mut overlay = FsMount::new("overlay")
+            .option("lowerdir", lower)
+            .option("upperdir", escape_overlay_path(&upper))
+            .option("workdir", escape_overlay_path(&work))
+            .attrs(MountAttr::NOSUID | MountAttr::NODEV);
+
+        // Unprivileged overlayfs can't set 'trusted.*' xattrs, use 'user.*' instead
+        if self.namespace.unshare_user {
+            overlay = overlay.flag("userxattr");
+        }
+
+        overlay
+            .mount_at(target.as_fd())
+            .with_context(|| format!("Failed to mount overlay at {}", dest.display()))?;
+
+        Ok(())
+    }
+
+    /// Where overlayfs finds the layer `dir`, the ID-mapped clone if there's one.
+    fn overlay_layer(&self, idx: usize, dir: &Path) -> Result<PathBuf> {
+        match self.idmapped_tree(idx, dir) {
+            Some(tree) => self.attach_layer(tree),
+            None => Ok(self.rebase_old(dir)),
+        }
+    }
+
+    /// Attaches a layer in the staging area, overlayfs only takes layers by path.
+    fn attach_layer(&self, tree: BorrowedFd<'_>) -> Result<PathBuf> {
+        let idx = self.overlays.get();
+        self.overlays.set(idx + 1);
+
+        let layer = self.staging().join(format!("layer-{idx}"));
+        utils::ensure_dir(&layer)?;
+        let target = RootFd::open(&layer)?.open_path(Path::new("/"))?;
+
+        BindMount::from_tree(tree, target.as_fd())
+            .mount()
+            .with_context(|| format!("Failed to attach overlay layer at {}", layer.display()))?;
+
+        Ok(layer)
+    }
+
+    /// Mounts a fresh tmpfs in the staging area, returns its '(upper, work)' dirs.
+    fn overlay_scratch(&self) -> Result<(PathBuf, PathBuf)> {
+        let idx = self.overlays.get();
+        self.overlays.set(idx + 1);
+
+        // Outside the new root, but the staging area is ours alone
+        let scratch = self.staging().join(format!("overlay-{idx}"));
+        utils::ensure_dir(&scratch)?;
+        let target = RootFd::open(&scratch)?.open_path(Path::new("/"))?;
+
+        FsMount::new("tmpfs")
+            .option("mode", "0755")
+            .attrs(MountAttr::NOSUID | MountAttr::NODEV)
+            .mount_at(target.as_fd())
+            .with_context(|| format!("Failed to mount overlay scratch at {}", scratch.display()))?;
+
+        let (upper, work) = (scratch.join("upper"), scratch.join("work"));
+        utils::ensure_dir(&upper)?;
+        utils::ensure_dir(&work)?;
+
+        Ok((upper, work))
+    }
+
     fn apply_proc(&self, dest: &Path) -> Result<()> {
-        // We've '/<new-root>/<dest>'
-        let target = self.rebase(dest);
-        utils::ensure_dir(&target)?;
+        let target = self.ensure_dir(dest)?;
 
         match &self.namespace.unshare_pid {
             true => {
-                mount::<str, std::path::Path, str, str>(
-                    Some("proc"),
-                    &target,
-                    Some("proc"),
-                    MsFlags::MS_NODEV | MsFlags::MS_NOSUID | MsFlags::MS_NOEXEC,
-                    None,
-                )
-                .with_context(|| format!("Failed to mount procfs at {}", target.display()))?;
+                FsMount::new("proc")
+                    .attrs(MountAttr::NODEV | MountAttr::NOSUID | MountAttr::NOEXEC)
+                    .mount_at(target.as_fd())
+                    .with_context(|| format!("Failed to mount procfs at {}", dest.display()))?;
             }
             false => {
-                todo!(
-                    "WIP: `BindMount` abstraction in mount/bind.rs to use system's proc-fs (w/ shared PID-NS)"
-                )
+                // A fresh procfs needs a PID-NS we own, share the host's instead
+                let source = self.rebase_old(Path::new("/proc"));
+                BindMount::new(&source, target.as_fd())
+                    .mount()
+                    .with_context(|| format!("Failed to bind host procfs at {}", dest.display()))?;
             }
         }
 
-        // TODO: cleanup '/proc' special dirs that could be exploited: sys, sysrq, irq, bus
+        let harden = !self.options.no_proc_hardening;
+
+        // Sysctls are global to the host w/ a shared procfs, never let the sandbox write them
+        if harden || !self.namespace.unshare_pid {
+            self.proc_read_only(dest)?;
+        }
+
+        if harden {
+            self.proc_mask(dest)?;
+        }
+
         Ok(())
     }
 
+    fn apply_sysfs(&self, dest: &Path) -> Result<()> {
+        let target = self.ensure_dir(dest)?;
+
+        match self.namespace.unshare_net {
+            // '/sys/class/net' reflects the mounter's NET-NS, so it has to be a fresh instance
+            true => FsMount::new("sysfs")
+                .attrs(MountAttr::RDONLY | MountAttr::NOSUID | MountAttr::NODEV | MountAttr::NOEXEC)
+                .mount_at(target.as_fd())
+                .with_context(|| format!("Failed to mount sysfs at {}", dest.display())),
+            false => {
+                let source = self.rebase_old(Path::new("/sys"));
+                BindMount::new(&source, target.as_fd())
+                    .read_only(true)
+                    .mount()
+                    .with_context(|| format!("Failed to bind host sysfs at {}", dest.display()))
+            }
+        }
+    }
+
+    fn proc_read_only(&self, proc: &Path) -> Result<()> {
+        const READ_ONLY: [&str; 4] = ["sys", "irq", "bus", "fs"];
+
+        for entry in READ_ONLY {
+            let path = proc.join(entry);
+            let Ok(dir) = self.open_dir(&path) else {
+                continue;
+            };
+
+            BindMount::from_fd(dir.as_fd(), dir.as_fd(), self.oldroot)
+                .read_only(true)
+                .mount()
+                .with_context(|| format!("Failed to make {} read-only", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    fn proc_mask(&self, proc: &Path) -> Result<()> {
+        let null = self.rebase_old(Path::new("/dev/null"));
+
+        for entry in self.options.proc_mask() {
+            let path = proc.join(&entry);
+
+            let target = match self.root.open_path(&path) {
+                Ok(target) => target,
+                Err(Errno::ENOENT) => continue,
+                Err(e) => {
+                    return Err(e).with_context(|| format!("Failed to stat {}", path.display()));
+                }
+            };
+            let is_dir = SFlag::from_bits_truncate(fstat(&target)?.st_mode) & SFlag::S_IFMT
+                == SFlag::S_IFDIR;
+
+            match is_dir {
+                true => FsMount::new("tmpfs")
+                    .option("mode", "0555")
+                    .attrs(
+                        MountAttr::RDONLY
+                            | MountAttr::NOSUID
+                            | MountAttr::NODEV
+                            | MountAttr::NOEXEC,
+                    )
+                    .mount_at(target.as_fd())
+                    .map_err(anyhow::Error::from),
+                false => BindMount::new(&null, target.as_fd())
+                    .read_only(true)
+                    .allow_dev(true)
+                    .mount()
+                    .map_err(anyhow::Error::from),
+            }
+            .with_context(|| format!("Failed to mask {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    /// Flips `dest` (and its submounts if `recursive`) read-only.
+    ///
+    /// A plain directory is bound onto itself first, otherwise the change would hit
+    /// whichever mount it lives on, e.g. the whole root.
+    fn apply_remount(&self, dest: &Path, recursive: bool) -> Result<()> {
+        let mut target = self.root.open_path(dest)?;
+
+        if !utils::is_mountpoint(target.as_fd())? {
+            bind::open_tree(target.as_fd())
+                .and_then(|tree| bind::move_mount(tree.as_fd(), target.as_fd()))
+                .with_context(|| format!("Failed to bind {} onto itself", dest.display()))?;
+
+            // The old handle still points below the new mount
+            target = self.root.open_path(dest)?;
+        }
+
+        SetAttr::new()
+            .set(MountAttr::RDONLY)
+            .recursive(recursive)
+            .apply_fd(target.as_fd())
+            .with_context(|| format!("Failed to remount {} read-only", dest.display()))
+    }
+
     // Too noisy, probably, it can be cleaned up later
     fn apply_symlink(&self, target: &Path, link: &Path) -> Result<()> {
-        match symlink(target, link) {
+        match self.root.symlink(target, link) {
             Ok(()) => Ok(()),
 
-            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => match link.read_link() {
+            Err(Errno::EEXIST) => match self.root.read_link(link) {
                 Ok(existing) if existing == target => Ok(()),
                 Ok(existing) => Err(anyhow!(
                     "can't make symlink at {}: existing destination is {}",
                     link.display(),
                     existing.display()
                 )),
-                Err(e) if e.kind() == io::ErrorKind::InvalidInput => Err(anyhow!(
+                Err(Errno::EINVAL) => Err(anyhow!(
                     "can't make symlink at {}: destination is not a symlink",
                     link.display()
                 )),
@@ -130,7 +875,149 @@ impl<'ctx> MountContext<'ctx> {
         }
     }
 
-    fn rebase(&self, dst: &Path) -> PathBuf {
-        self.newroot.join(dst.strip_prefix("/").unwrap_or(dst))
+    fn ensure_dir(&self, dest: &Path) -> Result<OwnedFd> {
+        self.ensure_dir_with_mode(dest, 0o755)
+    }
+
+    fn ensure_dir_with_mode(&self, dest: &Path, mode: u32) -> Result<OwnedFd> {
+        self.root
+            .ensure_dir(dest, mode)
+            .with_context(|| format!("Failed to create directory {}", dest.display()))
+    }
+
+    fn open_dir(&self, path: &Path) -> nix::Result<OwnedFd> {
+        self.root.resolve(
+            path,
+            OFlag::O_PATH | OFlag::O_DIRECTORY,
+            stat::Mode::empty(),
+        )
+    }
+
+    /// Host paths may go through symlinks like '/lib64 -> /usr/lib64', those are resolved
+    /// against the old root rather than the staging one.
+    fn rebase_old(&self, src: &Path) -> PathBuf {
+        utils::resolve_in_root(self.oldroot, src)
+    }
+
+    /// The staging tmpfs both roots live in, it's detached once the sandbox is pivoted.
+    fn staging(&self) -> &Path {
+        self.newroot.parent().unwrap_or(Path::new("/"))
+    }
+}
+
+/// Visible mounts `seal_root()` makes writable again, `None` if `newroot` isn't a mountpoint.
+///
+/// That's every writable entry, and what it brought along undeclared, unless it was read-only
+/// to begin w/. Overmounted ones stay sealed even there, they can't be reopened by path.
+fn writable_mounts<'t>(
+    tree: &'t MountTree,
+    newroot: &Path,
+    specs: &[MountSpec],
+) -> Option<Vec<&'t MountLine>> {
+    let declared: HashSet<&Path> = specs.iter().map(|spec| spec.entry.dest()).collect();
+    let writable: HashSet<&Path> = specs
+        .iter()
+        .filter(|spec| spec.writable)
+        .map(|spec| spec.entry.dest())
+        .collect();
+
+    let mut found = Vec::new();
+    let mut pending = vec![(tree.at(newroot)?, false)];
+
+    while let Some((mount, parent_writable)) = pending.pop() {
+        let dest = dest_in(newroot, mount);
+        let keep = writable.contains(dest.as_path())
+            || (parent_writable && !declared.contains(dest.as_path()));
+
+        if keep && !mount.options.contains(MountFlags::RDONLY) && tree.is_visible(mount.id) {
+            found.push(mount);
+        }
+
+        // Hidden mounts are walked too, what's mounted on top of them is a child
+        pending.extend(tree.children(mount.id).map(|child| (child, keep)));
+    }
+    Some(found)
+}
+
+/// Where `mount` shows up inside the new root.
+fn dest_in(newroot: &Path, mount: &MountLine) -> PathBuf {
+    Path::new("/").join(
+        mount
+            .mountpoint
+            .strip_prefix(newroot)
+            .unwrap_or(&mount.mountpoint),
+    )
+}
+
+/// Escapes the separators overlayfs splits its path options on.
+fn escape_overlay_path(path: &Path) -> String {
+    path.to_string_lossy()
+        .chars()
+        .fold(String::new(), |mut acc, c| {
+            if matches!(c, '\\' | ',' | ':') {
+                acc.push('\\');
+            }
+            acc.push(c);
+            acc
+        })
+}
+
+/// Only ever adds restrictions, whatever the entry already set is left as is.
+fn mount_attrs(attrs: &MountAttrs) -> SetAttr {
+    let mut set = MountAttr::empty();
+    set.set(MountAttr::NOEXEC, attrs.noexec);
+    set.set(MountAttr::NOSUID, attrs.nosuid);
+    set.set(MountAttr::NODEV, attrs.nodev);
+    set.set(MountAttr::NOSYMFOLLOW, attrs.nosymfollow);
+
+    let mut setattr = SetAttr::new().set(set).recursive(true);
+    if let Some(atime) = attrs.atime {
+        setattr = setattr.atime(match atime {
+            Atime::Relatime => MountAttr::empty(),
+            Atime::Noatime => MountAttr::NOATIME,
+            Atime::Strictatime => MountAttr::STRICTATIME,
+        });
+    }
+    setattr
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_keeps_only_visible_writable_mounts() {
+        let specs: Vec<MountSpec> = [
+            "bind:/srv:/srv",
+            "tmpfs:/srv",
+            "tmpfs:/srv/run,rw",
+            "tmpfs:/tmp,rw",
+        ]
+        .iter()
+        .map(|spec| spec.parse().unwrap())
+        .collect();
+
+        // The host's '/srv/cache' came along w/ the bind, but the tmpfs on '/srv' hides both
+        let table = "\
+100 90 0:40 /newroot /newroot rw,nosuid,nodev,relatime - tmpfs tmpfs rw
+101 100 254:0 /srv /newroot/srv rw,nosuid,nodev,relatime - ext4 /dev/vda rw
+102 101 254:1 / /newroot/srv/cache rw,nosuid,nodev,relatime - ext4 /dev/vdb rw
+103 101 0:41 / /newroot/srv rw,nosuid,nodev,relatime - tmpfs tmpfs rw
+104 103 0:42 / /newroot/srv/run rw,nosuid,nodev,relatime - tmpfs tmpfs rw
+105 100 0:43 / /newroot/tmp rw,nosuid,nodev,relatime - tmpfs tmpfs rw
+106 105 254:0 /x /newroot/tmp/x ro,nosuid,nodev,relatime - ext4 /dev/vda rw
+107 105 254:0 /y /newroot/tmp/y rw,nosuid,nodev,relatime - ext4 /dev/vda rw
+";
+        let tree = MountTree::try_from(MountInfo::from_reader(table.as_bytes())).unwrap();
+
+        let mut kept: Vec<u32> = writable_mounts(&tree, Path::new("/newroot"), &specs)
+            .unwrap()
+            .iter()
+            .map(|mount| mount.id)
+            .collect();
+        kept.sort();
+        assert_eq!(kept, [104, 105, 107]);
+
+        assert!(writable_mounts(&tree, Path::new("/elsewhere"), &specs).is_none());
     }
 }
@@ -0,0 +1,130 @@
+use nix::{NixPath, errno::Errno, libc, mount::MsFlags};
+use std::os::fd::{AsRawFd, BorrowedFd, RawFd};
+
+bitflags::bitflags! {
+    /// Per-mount attributes understood by `mount_setattr(2)`.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+    pub struct MountAttr: u64 {
+        const RDONLY = libc::MOUNT_ATTR_RDONLY;
+        const NOSUID = libc::MOUNT_ATTR_NOSUID;
+        const NODEV  = libc::MOUNT_ATTR_NODEV;
+        const NOEXEC = libc::MOUNT_ATTR_NOEXEC;
+        const NOATIME = libc::MOUNT_ATTR_NOATIME;
+        const STRICTATIME = libc::MOUNT_ATTR_STRICTATIME;
+        const NODIRATIME = libc::MOUNT_ATTR_NODIRATIME;
+        const NOSYMFOLLOW = libc::MOUNT_ATTR_NOSYMFOLLOW;
+        const IDMAP = libc::MOUNT_ATTR_IDMAP;
+
+        /// Mask of the atime mode, `RELATIME` is the absence of any bit in it.
+        const _ATIME = libc::MOUNT_ATTR__ATIME;
+    }
+}
+
+/// Builder around `mount_setattr(2)`, applies to a single mount or a whole subtree.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SetAttr {
+    set: MountAttr,
+    clear: MountAttr,
+    atime: Option<MountAttr>,
+    propagation: Option<MsFlags>,
+    userns: Option<RawFd>,
+    recursive: bool,
+}
+
+impl SetAttr {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(mut self, attr: MountAttr) -> Self {
+        self.set |= attr;
+        self
+    }
+
+    pub fn clear(mut self, attr: MountAttr) -> Self {
+        self.clear |= attr;
+        self
+    }
+
+    /// Replace the atime mode, one of `NOATIME`, `STRICTATIME` or empty for relatime.
+    pub fn atime(mut self, atime: MountAttr) -> Self {
+        self.atime = Some(atime & MountAttr::_ATIME);
+        self
+    }
+
+    /// One of `MS_PRIVATE`, `MS_SLAVE`, `MS_SHARED` or `MS_UNBINDABLE`.
+    pub fn propagation(mut self, propagation: MsFlags) -> Self {
+        self.propagation = Some(propagation);
+        self
+    }
+
+    /// ID-map the mount through the user namespace at `userns`.
+    ///
+    /// Only works on a detached mount that was never idmapped, and the caller needs
+    /// `CAP_SYS_ADMIN` over the filesystem as well as over `userns`.
+    pub fn idmap(mut self, userns: BorrowedFd<'_>) -> Self {
+        self.set |= MountAttr::IDMAP;
+        self.userns = Some(userns.as_raw_fd());
+        self
+    }
+
+    /// Also apply to every submount (`AT_RECURSIVE`).
+    pub fn recursive(mut self, recursive: bool) -> Self {
+        self.recursive = recursive;
+        self
+    }
+
+    pub fn is_noop(&self) -> bool {
+        self.set.is_empty()
+            && self.clear.is_empty()
+            && self.atime.is_none()
+            && self.propagation.is_none()
+    }
+
+    /// Apply to a detached or attached mount referred to by `fd`.
+    pub fn apply_fd(&self, fd: BorrowedFd<'_>) -> nix::Result<()> {
+        self.apply_at(fd.as_raw_fd(), "", libc::AT_EMPTY_PATH as libc::c_uint)
+    }
+
+    fn apply_at<P: ?Sized + NixPath>(
+        &self,
+        dirfd: RawFd,
+        path: &P,
+        mut flags: libc::c_uint,
+    ) -> nix::Result<()> {
+        if self.is_noop() {
+            return Ok(());
+        }
+
+        if self.recursive {
+            flags |= libc::AT_RECURSIVE as libc::c_uint;
+        }
+
+        let (mut set, mut clear) = (self.set, self.clear);
+        if let Some(atime) = self.atime {
+            // The atime bits aren't independent, the kernel wants the whole field cleared
+            clear |= MountAttr::_ATIME;
+            set = (set - MountAttr::_ATIME) | atime;
+        }
+
+        let attr = libc::mount_attr {
+            attr_set: set.bits(),
+            attr_clr: clear.bits(),
+            propagation: self.propagation.map_or(0, |p| p.bits()),
+            userns_fd: self.userns.map_or(0, |fd| fd as u64),
+        };
+
+        let res = path.with_nix_path(|cstr| unsafe {
+            libc::syscall(
+                libc::SYS_mount_setattr,
+                dirfd,
+                cstr.as_ptr(),
+                flags,
+                &attr as *const libc::mount_attr,
+                std::mem::size_of::<libc::mount_attr>(),
+            )
+        })?;
+
+        Errno::result(res).map(drop)
+    }
+}
@@ -0,0 +1,635 @@
+use super::{
+    info::{MountFlags, MountLine},
+    tree::MountTree,
+};
+use crate::config::{
+    Mode, MountAttrs, MountEntry, MountSource, MountSpec, NamespaceOptions, Propagation,
+};
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+    path::{Path, PathBuf},
+};
+
+/// What the plan says should be mounted at `dest`.
+#[derive(Debug, Clone)]
+struct Expected {
+    kind: &'static str,
+    dest: PathBuf,
+    required: MountFlags,
+    forbidden: MountFlags,
+    /// Flags every mount below it has to carry, `None` if it can't have any of its own.
+    ///
+    /// Recursive binds bring the host's submounts along, proc and dev mount their own.
+    inherited: Option<MountFlags>,
+    /// Set w/ `propagation=`, checked against mountinfo's optional fields.
+    propagation: Option<Propagation>,
+    optional: bool,
+    /// Passed w/ `rw`, `--ro-root` doesn't touch it or what it brought along.
+    writable: bool,
+}
+
+/// Checks the mounts the sandbox ended up w/ against the entries it was given.
+///
+/// Only visible mounts are checked against their entries, hidden ones only have to be
+/// declared. W/ `--ro-root` they still have to be read-only.
+#[derive(Debug)]
+pub struct MountAudit {
+    expected: Vec<Expected>,
+    /// Where the root mount is taken from in the staging tmpfs, e.g. '/newroot'.
+    root: PathBuf,
+    /// Where the host's root was kept in the staging tmpfs, e.g. '/oldroot'.
+    old_root: PathBuf,
+    ro_root: bool,
+}
+
+impl MountAudit {
+    /// `specs` in the order they were applied, i.e. after `MountPlan`.
+    pub fn new(
+        specs: &[MountSpec],
+        namespace: &NamespaceOptions,
+        new_root_name: &str,
+        old_root_name: &str,
+    ) -> Self {
+        let mut expected: Vec<Expected> = Vec::new();
+
+        for spec in specs {
+            if let MountEntry::Remount { dest, recursive } = &spec.entry {
+                remount(&mut expected, dest, *recursive, spec.optional);
+                continue;
+            }
+
+            let Some((required, forbidden, inherited)) = entry_flags(&spec.entry, namespace) else {
+                continue;
+            };
+            let attrs = attr_flags(&spec.attrs);
+
+            expected.push(Expected {
+                kind: spec.entry.kind(),
+                dest: spec.entry.dest().to_path_buf(),
+                required: required | attrs,
+                forbidden,
+                inherited: inherited.map(|inherited| inherited | attrs),
+                propagation: spec.propagation,
+                optional: spec.optional,
+                writable: spec.writable,
+            });
+        }
+
+        Self {
+            expected,
+            root: Path::new("/").join(new_root_name),
+            old_root: Path::new("/").join(old_root_name),
+            ro_root: false,
+        }
+    }
+
+    /// Expects every mount to be read-only, except the ones `MountContext::seal_root` skips.
+    pub fn ro_root(mut self, ro_root: bool) -> Self {
+        self.ro_root = ro_root;
+
+        if ro_root {
+            for expected in self.expected.iter_mut().filter(|e| !e.writable) {
+                expected.required |= MountFlags::RDONLY;
+                expected.forbidden.remove(MountFlags::RDONLY);
+                if let Some(inherited) = &mut expected.inherited {
+                    *inherited |= MountFlags::RDONLY;
+                }
+            }
+        }
+        self
+    }
+
+    pub fn check(&self, tree: &MountTree) -> AuditReport {
+        let mut report = AuditReport::default();
+
+        let root = tree.root();
+        if root.fstype != "tmpfs" || root.root != self.root {
+            report.findings.push(Finding::ForeignRoot(root.clone()));
+        }
+
+        if self.ro_root && !root.options.contains(MountFlags::RDONLY) {
+            report.findings.push(Finding::Flags {
+                kind: "root",
+                mount: root.clone(),
+                missing: MountFlags::RDONLY,
+                unexpected: MountFlags::empty(),
+            });
+        }
+
+        // Hidden isn't sealed, unmounting what's on top would expose them
+        if self.ro_root {
+            report.findings.extend(
+                tree.overmounted()
+                    .filter(|mount| !mount.options.contains(MountFlags::RDONLY))
+                    .map(|mount| Finding::Flags {
+                        kind: "overmounted",
+                        mount: mount.clone(),
+                        missing: MountFlags::RDONLY,
+                        unexpected: MountFlags::empty(),
+                    }),
+            );
+        }
+
+        let declared: HashSet<&Path> = self
+            .expected
+            .iter()
+            .map(|expected| expected.dest.as_path())
+            .collect();
+
+        // Wherever they're mounted, flags inherited from a bind above don't make them fine
+        let leftovers: HashSet<u32> = tree
+            .iter()
+            .filter(|mount| mount.id != root.id && self.is_leftover(root, mount, &declared))
+            .map(|mount| mount.id)
+            .collect();
+        report.findings.extend(
+            tree.iter()
+                .filter(|mount| leftovers.contains(&mount.id))
+                .map(|mount| Finding::Leftover(mount.clone())),
+        );
+
+        let mut owners = HashMap::new();
+        for expected in &self.expected {
+            let Some(mount) = tree.at(&expected.dest) else {
+                if !expected.optional {
+                    report.findings.push(Finding::Missing {
+                        kind: expected.kind,
+                        dest: expected.dest.clone(),
+                    });
+                }
+                continue;
+            };
+
+            let missing = expected.required.difference(mount.options);
+            let unexpected = expected.forbidden.intersection(mount.options);
+            if !missing.is_empty() || !unexpected.is_empty() {
+                report.findings.push(Finding::Flags {
+                    kind: expected.kind,
+                    mount: mount.clone(),
+                    missing,
+                    unexpected,
+                });
+            }
+
+            if let Some(propagation) = expected.propagation
+                && !has_propagation(mount, propagation)
+            {
+                report.findings.push(Finding::Propagation {
+                    kind: expected.kind,
+                    mount: mount.clone(),
+                    expected: propagation,
+                });
+            }
+
+            if let Some(inherited) = expected.inherited {
+                // Nothing above a writable entry applies below it once the root is sealed
+                owners.insert(mount.id, (inherited, self.ro_root && expected.writable));
+            }
+        }
+
+        for mount in tree.iter() {
+            if mount.id == root.id
+                || leftovers.contains(&mount.id)
+                || declared.contains(mount.mountpoint.as_path())
+            {
+                continue;
+            }
+
+            let Some(required) = inherited(tree, &owners, mount) else {
+                report.findings.push(Finding::Undeclared(mount.clone()));
+                continue;
+            };
+
+            let missing = required.difference(mount.options);
+            if !missing.is_empty() && tree.is_visible(mount.id) {
+                report.findings.push(Finding::Flags {
+                    kind: "submount",
+                    mount: mount.clone(),
+                    missing,
+                    unexpected: MountFlags::empty(),
+                });
+            }
+        }
+
+        report
+    }
+
+    /// Stacked on '/', taken from the staging tmpfs outside the new root, or mounted where
+    /// staging kept either root.
+    fn is_leftover(&self, root: &MountLine, mount: &MountLine, declared: &HashSet<&Path>) -> bool {
+        let from_staging = (mount.major, mount.minor) == (root.major, root.minor)
+            && !mount.root.starts_with(&self.root);
+        let at_staging = !declared.contains(mount.mountpoint.as_path())
+            && [&self.root, &self.old_root]
+                .iter()
+                .any(|path| mount.mountpoint.starts_with(path));
+
+        mount.mountpoint == Path::new("/") || from_staging || at_staging
+    }
+}
+
+/// Folds a remount into the entry at `dest`, or declares the bind it creates on a plain directory.
+fn remount(expected: &mut Vec<Expected>, dest: &Path, recursive: bool, optional: bool) {
+    if recursive {
+        for below in expected.iter_mut().filter(|e| e.dest.starts_with(dest)) {
+            below.required |= MountFlags::RDONLY;
+            below.forbidden.remove(MountFlags::RDONLY);
+            if let Some(inherited) = &mut below.inherited {
+                *inherited |= MountFlags::RDONLY;
+            }
+        }
+    }
+
+    match expected.iter_mut().find(|e| e.dest == dest) {
+        Some(existing) => {
+            existing.required |= MountFlags::RDONLY;
+            existing.forbidden.remove(MountFlags::RDONLY);
+        }
+        // The bind onto itself clones whatever was mounted below `dest`
+        None => expected.push(Expected {
+            kind: "remount",
+            dest: dest.to_path_buf(),
+            required: MountFlags::RDONLY,
+            forbidden: MountFlags::empty(),
+            inherited: Some(match recursive {
+                true => MountFlags::RDONLY,
+                false => MountFlags::empty(),
+            }),
+            propagation: None,
+            optional,
+            writable: false,
+        }),
+    }
+}
+
+/// `(required, forbidden, inherited)` flags, mirroring what `MountContext` sets per kind.
+fn entry_flags(
+    entry: &MountEntry,
+    namespace: &NamespaceOptions,
+) -> Option<(MountFlags, MountFlags, Option<MountFlags>)> {
+    let nosuid_nodev = MountFlags::NOSUID | MountFlags::NODEV;
+    let access = |mode: &Mode| match mode {
+        Mode::ReadOnly => (MountFlags::RDONLY, MountFlags::empty()),
+        Mode::ReadWrite => (MountFlags::empty(), MountFlags::RDONLY),
+    };
+
+    let flags = match entry {
+        MountEntry::Bind { src, mode, .. } => {
+            let (ro, rw) = access(mode);
+            let required = match src {
+                MountSource::Path {
+                    mount_dev: true, ..
+                } => MountFlags::NOSUID | ro,
+                _ => nosuid_nodev | ro,
+            };
+            (required, rw, Some(required))
+        }
+        MountEntry::Cgroup { mode, .. } => {
+            let (ro, rw) = access(mode);
+            (nosuid_nodev | MountFlags::NOEXEC | ro, rw, None)
+        }
+        MountEntry::Dev { .. } => (nosuid_nodev, MountFlags::empty(), Some(MountFlags::NOSUID)),
+        MountEntry::Devpts { .. } => (
+            MountFlags::NOSUID | MountFlags::NOEXEC,
+            MountFlags::empty(),
+            None,
+        ),
+        MountEntry::File {
+            read_only: true, ..
+        } => (nosuid_nodev | MountFlags::RDONLY, MountFlags::empty(), None),
+        MountEntry::Proc { .. } => {
+            let required = match namespace.unshare_pid {
+                true => nosuid_nodev | MountFlags::NOEXEC,
+                false => nosuid_nodev,
+            };
+            (required, MountFlags::empty(), Some(MountFlags::NOSUID))
+        }
+        MountEntry::Sysfs { .. } => {
+            let required = nosuid_nodev | MountFlags::RDONLY;
+            match namespace.unshare_net {
+                true => (required | MountFlags::NOEXEC, MountFlags::empty(), None),
+                false => (required, MountFlags::empty(), Some(required)),
+            }
+        }
+        MountEntry::Mqueue { .. } | MountEntry::Overlay { .. } | MountEntry::Tmpfs { .. } => {
+            (nosuid_nodev, MountFlags::empty(), None)
+        }
+        MountEntry::Chmod(_)
+        | MountEntry::Dir { .. }
+        | MountEntry::File { .. }
+        | MountEntry::Remount { .. }
+        | MountEntry::Symlink { .. } => return None,
+    };
+
+    Some(flags)
+}
+
+fn attr_flags(attrs: &MountAttrs) -> MountFlags {
+    let mut flags = MountFlags::empty();
+    flags.set(MountFlags::NOEXEC, attrs.noexec);
+    flags.set(MountFlags::NOSUID, attrs.nosuid);
+    flags.set(MountFlags::NODEV, attrs.nodev);
+    flags.set(MountFlags::NOSYMFOLLOW, attrs.nosymfollow);
+    flags
+}
+
+/// Whether the tags in mountinfo match what `propagation=` set, `MS_SLAVE` leaves a mount
+/// that wasn't shared private.
+fn has_propagation(mount: &MountLine, propagation: Propagation) -> bool {
+    let (shared, slave, unbindable) = (
+        mount.peer_group().is_some(),
+        mount.master().is_some(),
+        mount.is_unbindable(),
+    );
+
+    match propagation {
+        Propagation::Shared => shared,
+        Propagation::Slave => !shared && !unbindable,
+        Propagation::Private => !shared && !slave && !unbindable,
+        Propagation::Unbindable => unbindable,
+    }
+}
+
+/// Flags `mount` inherits from the declared mounts above it, `None` if none of them
+/// can have undeclared submounts.
+fn inherited(
+    tree: &MountTree,
+    owners: &HashMap<u32, (MountFlags, bool)>,
+    mount: &MountLine,
+) -> Option<MountFlags> {
+    let mut required = None;
+    let mut parent = tree.get(mount.parent_id);
+
+    while let Some(current) = parent.filter(|current| current.id != current.parent_id) {
+        if let Some(&(flags, last)) = owners.get(&current.id) {
+            required = Some(required.unwrap_or(MountFlags::empty()) | flags);
+            if last {
+                break;
+            }
+        }
+        parent = tree.get(current.parent_id);
+    }
+
+    required
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Finding {
+    /// '/' isn't the tmpfs the new root was staged on.
+    ForeignRoot(MountLine),
+    /// Staging or old root still mounted somewhere in the sandbox.
+    Leftover(MountLine),
+    Missing {
+        kind: &'static str,
+        dest: PathBuf,
+    },
+    Undeclared(MountLine),
+    Propagation {
+        kind: &'static str,
+        mount: MountLine,
+        expected: Propagation,
+    },
+    Flags {
+        kind: &'static str,
+        mount: MountLine,
+        missing: MountFlags,
+        unexpected: MountFlags,
+    },
+}
+
+/// Mismatches between the mount plan and the sandbox, empty if there are none.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct AuditReport {
+    findings: Vec<Finding>,
+}
+
+impl AuditReport {
+    pub fn is_empty(&self) -> bool {
+        self.findings.is_empty()
+    }
+}
+
+/// One line per mismatch, `-` for what's missing, `+` for what shouldn't be there.
+impl fmt::Display for AuditReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let line = |mount: &MountLine| {
+            format!(
+                "{} {} {} {}",
+                mount.mountpoint.display(),
+                mount.fstype,
+                mount.source,
+                mount.options
+            )
+        };
+
+        for finding in &self.findings {
+            match finding {
+                Finding::ForeignRoot(mount) => {
+                    writeln!(f, "! {} (root isn't the staged tmpfs)", line(mount))?
+                }
+                Finding::Leftover(mount) => {
+                    writeln!(f, "+ {} (left over from staging)", line(mount))?
+                }
+                Finding::Missing { kind, dest } => {
+                    writeln!(f, "- {} ({kind}) not mounted", dest.display())?
+                }
+                Finding::Undeclared(mount) => writeln!(f, "+ {} (not declared)", line(mount))?,
+                Finding::Propagation {
+                    kind,
+                    mount,
+                    expected,
+                } => writeln!(
+                    f,
+                    "~ {} ({kind}) isn't {}",
+                    line(mount),
+                    format!("{expected:?}").to_ascii_lowercase()
+                )?,
+                Finding::Flags {
+                    kind,
+                    mount,
+                    missing,
+                    unexpected,
+                } => {
+                    write!(f, "~ {} ({kind})", line(mount))?;
+                    if !missing.is_empty() {
+                        write!(f, " lacks {}", flag_names(*missing))?;
+                    }
+                    if !unexpected.is_empty() {
+                        write!(f, " shouldn't be {}", flag_names(*unexpected))?;
+                    }
+                    writeln!(f)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Unlike `MountFlags`' `Display`, only lists the flags that are set.
+fn flag_names(flags: MountFlags) -> String {
+    flags
+        .iter_names()
+        .map(|(name, _)| match name {
+            "RDONLY" => "ro".to_owned(),
+            name => name.to_ascii_lowercase(),
+        })
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mount::info::MountInfo;
+
+    fn namespace() -> NamespaceOptions {
+        NamespaceOptions {
+            unshare_all: false,
+            unshare_ipc: false,
+            unshare_pid: true,
+            unshare_net: false,
+            unshare_uts: false,
+            unshare_cgroup: false,
+            unshare_user: true,
+            unshare_files: false,
+            unshare_fs: false,
+            unshare_time: false,
+            unshare_sysvsem: false,
+        }
+    }
+
+    fn audit(specs: &[&str], table: &str) -> AuditReport {
+        let specs: Vec<MountSpec> = specs.iter().map(|spec| spec.parse().unwrap()).collect();
+        let tree = MountTree::try_from(MountInfo::from_reader(table.as_bytes())).unwrap();
+        MountAudit::new(&specs, &namespace(), "newroot", "oldroot").check(&tree)
+    }
+
+    const SPECS: [&str; 4] = [
+        "bind:/usr:/usr,ro",
+        "tmpfs:/tmp",
+        "proc:/proc",
+        "remount:/etc,ro",
+    ];
+
+    const SANDBOX: &str = "\
+100 90 0:40 /newroot / rw,nosuid,nodev,relatime - tmpfs tmpfs rw
+101 100 254:0 /usr /usr ro,nosuid,nodev,relatime - ext4 /dev/vda rw
+102 101 254:1 / /usr/local ro,nosuid,nodev,relatime - ext4 /dev/vdb rw
+103 100 0:41 / /tmp rw,nosuid,nodev,relatime - tmpfs tmpfs rw
+104 100 0:42 / /proc rw,nosuid,nodev,noexec,relatime - proc proc rw
+105 104 0:43 / /proc/kcore ro,nosuid,nodev,noexec,relatime - tmpfs tmpfs ro
+106 100 0:40 /newroot/etc /etc ro,nosuid,nodev,relatime - tmpfs tmpfs rw
+";
+
+    #[test]
+    fn test_audit_accepts_plan() {
+        let report = audit(&SPECS, SANDBOX);
+        assert!(report.is_empty(), "{report}");
+    }
+
+    #[test]
+    fn test_audit_reports_mismatches() {
+        let table = SANDBOX
+            .replace(
+                "101 100 254:0 /usr /usr ro,nosuid",
+                "101 100 254:0 /usr /usr rw,nosuid",
+            )
+            .replace("103 100 0:41 / /tmp", "103 100 0:41 / /var");
+
+        let report = audit(&SPECS, &table).to_string();
+        assert!(report.contains("~ /usr ext4 /dev/vda rw,nosuid,nodev,relatime (bind) lacks ro\n"));
+        assert!(report.contains("- /tmp (tmpfs) not mounted\n"));
+        assert!(report.contains("+ /var tmpfs tmpfs rw,nosuid,nodev,relatime (not declared)\n"));
+        // Host submounts come along w/ the bind, and keep their own flags
+        assert!(!report.contains("/usr/local"));
+
+        let staging = SANDBOX.to_owned() + "107 100 0:44 / / rw,relatime - tmpfs tmpfs rw\n";
+        let report = audit(&SPECS, &staging).to_string();
+        assert!(report.contains("+ / tmpfs tmpfs rw,relatime (left over from staging)\n"));
+        assert!(report.contains("- /usr (bind) not mounted\n"));
+
+        // Below a recursive bind, w/ the flags it passes down
+        let staging = SANDBOX.to_owned()
+            + "107 101 0:40 / /usr/share ro,nosuid,nodev,relatime - tmpfs tmpfs rw\n"
+            + "108 100 254:0 / /oldroot ro,nosuid,nodev,relatime - ext4 /dev/vda rw\n";
+        let report = audit(&SPECS, &staging).to_string();
+        assert_eq!(
+            report,
+            "+ /usr/share tmpfs tmpfs ro,nosuid,nodev,relatime (left over from staging)\n\
+             + /oldroot ext4 /dev/vda ro,nosuid,nodev,relatime (left over from staging)\n"
+        );
+    }
+
+    #[test]
+    fn test_audit_propagation() {
+        let specs = [
+            "bind:/srv:/srv,rw,propagation=shared",
+            "tmpfs:/tmp,propagation=unbindable",
+            "tmpfs:/run,propagation=private",
+        ];
+        let table = "\
+100 90 0:40 /newroot / rw,nosuid,nodev,relatime - tmpfs tmpfs rw
+101 100 254:0 /srv /srv rw,nosuid,nodev,relatime shared:3 master:1 - ext4 /dev/vda rw
+102 100 0:41 / /tmp rw,nosuid,nodev,relatime unbindable - tmpfs tmpfs rw
+103 100 0:42 / /run rw,nosuid,nodev,relatime - tmpfs tmpfs rw
+";
+        let report = audit(&specs, table);
+        assert!(report.is_empty(), "{report}");
+
+        let table = table
+            .replace(" shared:3 master:1 ", " master:1 ")
+            .replace(" unbindable ", " ")
+            .replace(
+                "relatime - tmpfs tmpfs rw\n",
+                "relatime master:2 - tmpfs tmpfs rw\n",
+            );
+        let report = audit(&specs, &table).to_string();
+        assert!(
+            report.contains("~ /srv ext4 /dev/vda rw,nosuid,nodev,relatime (bind) isn't shared\n")
+        );
+        assert!(
+            report
+                .contains("~ /tmp tmpfs tmpfs rw,nosuid,nodev,relatime (tmpfs) isn't unbindable\n")
+        );
+        assert!(
+            report.contains("~ /run tmpfs tmpfs rw,nosuid,nodev,relatime (tmpfs) isn't private\n")
+        );
+    }
+
+    #[test]
+    fn test_audit_ro_root() {
+        let specs: Vec<MountSpec> = ["bind:/usr:/usr", "tmpfs:/tmp,rw", "bind:/srv:/srv,rw"]
+            .iter()
+            .map(|spec| spec.parse().unwrap())
+            .collect();
+        let table = "\
+100 90 0:40 /newroot / ro,nosuid,nodev,relatime - tmpfs tmpfs rw
+101 100 254:0 /usr /usr ro,nosuid,nodev,relatime - ext4 /dev/vda rw
+102 101 254:1 / /usr/local ro,nosuid,nodev,relatime - ext4 /dev/vdb rw
+103 100 0:41 / /tmp rw,nosuid,nodev,relatime - tmpfs tmpfs rw
+104 100 254:0 /srv /srv rw,nosuid,nodev,relatime - ext4 /dev/vda rw
+105 104 254:2 / /srv/cache rw,nosuid,nodev,relatime - ext4 /dev/vdc rw
+";
+        let tree = MountTree::try_from(MountInfo::from_reader(table.as_bytes())).unwrap();
+        let audit = MountAudit::new(&specs, &namespace(), "newroot", "oldroot").ro_root(true);
+        let report = audit.check(&tree);
+        assert!(report.is_empty(), "{report}");
+
+        // A default 'rw' bind isn't marked, so it gets sealed like the rest
+        let table = table.replace("/usr /usr ro,", "/usr /usr rw,");
+        let tree = MountTree::try_from(MountInfo::from_reader(table.as_bytes())).unwrap();
+        let report = audit.check(&tree).to_string();
+        assert!(report.contains("~ /usr ext4 /dev/vda rw,nosuid,nodev,relatime (bind) lacks ro\n"));
+
+        // Writable or not, what's below another mount stays sealed
+        let table = table.replace("/usr /usr rw,", "/usr /usr ro,")
+            + "106 103 0:44 / /tmp rw,nosuid,nodev,relatime - tmpfs tmpfs rw\n";
+        let tree = MountTree::try_from(MountInfo::from_reader(table.as_bytes())).unwrap();
+        let report = audit.check(&tree).to_string();
+        assert_eq!(
+            report,
+            "~ /tmp tmpfs tmpfs rw,nosuid,nodev,relatime (overmounted) lacks ro\n"
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use super::attr::{MountAttr, SetAttr};
+use nix::{NixPath, errno::Errno, libc, sys::stat::fstat};
+use std::{
+    collections::HashMap,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+    path::{Path, PathBuf},
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum BindError {
+    #[error("bind source '{0}' does not exist")]
+    SourceNotFound(String),
+
+    #[error("bind source fd={0} was replaced before it could be mounted")]
+    SourceMismatch(RawFd),
+
+    #[error("mount operation failed: {stage}")]
+    Mount {
+        stage: &'static str,
+        #[source]
+        source: nix::Error,
+    },
+
+    #[error("filesystem operation failed: {stage}")]
+    Fs {
+        stage: &'static str,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+#[derive(Debug)]
+enum BindSource<'a> {
+    Path(&'a Path),
+    Fd {
+        fd: BorrowedFd<'a>,
+        oldroot: &'a Path,
+    },
+    Tree(BorrowedFd<'a>),
+}
+
+/// Recursive bind of a source onto `dest`, attributes are set before it's attached.
+///
+/// `dest` is a handle on an existing mountpoint, creating it is up to the caller.
+#[derive(Debug)]
+pub struct BindMount<'a> {
+    src: BindSource<'a>,
+    dest: BorrowedFd<'a>,
+    read_only: bool,
+    allow_dev: bool,
+}
+
+impl<'a> BindMount<'a> {
+    pub fn new(src: &'a Path, dest: BorrowedFd<'a>) -> Self {
+        Self {
+            src: BindSource::Path(src),
+            dest,
+            read_only: false,
+            allow_dev: false,
+        }
+    }
+
+    /// Bind the file or directory referred to by `fd`.
+    ///
+    /// `oldroot` is where the host root is reachable, it's only used when the fd
+    /// belongs to a different mount namespace and has to be resolved by path.
+    pub fn from_fd(fd: BorrowedFd<'a>, dest: BorrowedFd<'a>, oldroot: &'a Path) -> Self {
+        Self {
+            src: BindSource::Fd { fd, oldroot },
+            dest,
+            read_only: false,
+            allow_dev: false,
+        }
+    }
+
+    /// Attach a detached tree previously captured by [`SharedSources`].
+    pub fn from_tree(tree: BorrowedFd<'a>, dest: BorrowedFd<'a>) -> Self {
+        Self {
+            src: BindSource::Tree(tree),
+            dest,
+            read_only: false,
+            allow_dev: false,
+        }
+    }
+
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    pub fn allow_dev(mut self, allow_dev: bool) -> Self {
+        self.allow_dev = allow_dev;
+        self
+    }
+
+    pub fn mount(self) -> Result<(), BindError> {
+        match self.src {
+            BindSource::Path(src) => self.mount_path(src),
+            BindSource::Tree(tree) => self.attach_tree(tree),
+            BindSource::Fd { fd, oldroot } => match open_tree(fd) {
+                Ok(tree) => self.attach_tree(tree.as_fd()),
+                // The fd was opened before CLONE_NEWNS, so it points into the host's
+                // mount namespace which the kernel refuses to clone from.
+                Err(Errno::EINVAL) => self.mount_fd_by_path(fd, oldroot),
+                Err(e) => Err(BindError::Mount {
+                    stage: "open_tree <fd>",
+                    source: e,
+                }),
+            },
+        }
+    }
+
+    fn mount_path(&self, src: &Path) -> Result<(), BindError> {
+        let tree = self.clone_path(src)?;
+        self.attach_tree(tree.as_fd())
+    }
+
+    fn clone_path(&self, src: &Path) -> Result<OwnedFd, BindError> {
+        open_tree_at(src).map_err(|e| match e {
+            Errno::ENOENT => BindError::SourceNotFound(src.display().to_string()),
+            e => BindError::Mount {
+                stage: "open_tree <src>",
+                source: e,
+            },
+        })
+    }
+
+    /// Flags are set on the detached tree, so it's never visible w/ the wrong attributes.
+    /// They're applied to every submount, a plain MS_REMOUNT would only touch the top one.
+    fn attach_tree(&self, tree: BorrowedFd<'_>) -> Result<(), BindError> {
+        self.attrs().apply_fd(tree).map_err(|e| BindError::Mount {
+            stage: "mount_setattr <fd>",
+            source: e,
+        })?;
+
+        move_mount(tree, self.dest).map_err(|e| BindError::Mount {
+            stage: "move_mount <fd> to <dest>",
+            source: e,
+        })
+    }
+
+    /// Fallback via '/proc/self/fd/<N>', the clone is checked against the fd before it's attached.
+    fn mount_fd_by_path(&self, fd: BorrowedFd<'_>, oldroot: &Path) -> Result<(), BindError> {
+        let raw_fd = fd.as_raw_fd();
+        let link = oldroot.join(format!("proc/self/fd/{raw_fd}"));
+
+        let target = std::fs::read_link(&link).map_err(|e| BindError::Fs {
+            stage: "readlink /proc/self/fd/<N>",
+            source: e,
+        })?;
+        let src = oldroot.join(target.strip_prefix("/").unwrap_or(&target));
+
+        let tree = self.clone_path(&src)?;
+
+        let expected = fstat(fd).map_err(|e| BindError::Mount {
+            stage: "fstat <fd>",
+            source: e,
+        })?;
+        let cloned = fstat(&tree).map_err(|e| BindError::Mount {
+            stage: "fstat <tree>",
+            source: e,
+        })?;
+
+        if (expected.st_dev, expected.st_ino) != (cloned.st_dev, cloned.st_ino) {
+            return Err(BindError::SourceMismatch(raw_fd));
+        }
+
+        self.attach_tree(tree.as_fd())
+    }
+
+    fn attrs(&self) -> SetAttr {
+        let mut attrs = MountAttr::NOSUID;
+
+        if self.read_only {
+            attrs |= MountAttr::RDONLY;
+        }
+
+        if !self.allow_dev {
+            attrs |= MountAttr::NODEV;
+        }
+
+        SetAttr::new().set(attrs).recursive(true)
+    }
+}
+
+/// Detached copies of host trees, taken before '/' is made a slave.
+///
+/// A clone of a shared mount joins its peer group, so mounts created below the
+/// bind inside the sandbox propagate back to the host. In a user namespace the
+/// kernel already reduced every shared mount to a slave, so this only keeps
+/// receiving from the host.
+#[derive(Debug, Default)]
+pub struct SharedSources {
+    trees: HashMap<usize, OwnedFd>,
+}
+
+impl SharedSources {
+    /// `sources` are `(entry index, host path)` pairs.
+    pub fn capture<'p>(
+        sources: impl IntoIterator<Item = (usize, &'p Path)>,
+    ) -> Result<Self, BindError> {
+        let mut trees = HashMap::new();
+
+        for (idx, src) in sources {
+            let tree = open_tree_at(src).map_err(|e| match e {
+                Errno::ENOENT => BindError::SourceNotFound(src.display().to_string()),
+                e => BindError::Mount {
+                    stage: "open_tree <shared-src>",
+                    source: e,
+                },
+            })?;
+            trees.insert(idx, tree);
+        }
+
+        Ok(Self { trees })
+    }
+
+    pub fn get(&self, idx: usize) -> Option<BorrowedFd<'_>> {
+        self.trees.get(&idx).map(|tree| tree.as_fd())
+    }
+}
+
+/// Detached copies of host trees w/ an ID mapping, set up by the parent before clone().
+///
+/// Idmapping needs `CAP_SYS_ADMIN` over the source filesystem, which the child gives up
+/// w/ its own user namespace. It inherits the fds and only has to attach the trees.
+#[derive(Debug, Default)]
+pub struct IdmappedSources {
+    trees: HashMap<(usize, PathBuf), OwnedFd>,
+}
+
+impl IdmappedSources {
+    /// Clones the tree at `src` for the entry at `idx`, mapped through `userns`.
+    pub fn capture(
+        &mut self,
+        idx: usize,
+        src: &Path,
+        userns: BorrowedFd<'_>,
+    ) -> Result<(), BindError> {
+        let tree = open_tree_at(src).map_err(|e| match e {
+            Errno::ENOENT => BindError::SourceNotFound(src.display().to_string()),
+            e => BindError::Mount {
+                stage: "open_tree <idmap-src>",
+                source: e,
+            },
+        })?;
+
+        SetAttr::new()
+            .idmap(userns)
+            .recursive(true)
+            .apply_fd(tree.as_fd())
+            .map_err(|e| BindError::Mount {
+                stage: "mount_setattr <idmap>",
+                source: e,
+            })?;
+
+        self.trees.insert((idx, src.to_path_buf()), tree);
+        Ok(())
+    }
+
+    pub fn get(&self, idx: usize, src: &Path) -> Option<BorrowedFd<'_>> {
+        self.trees
+            .get(&(idx, src.to_path_buf()))
+            .map(|tree| tree.as_fd())
+    }
+}
+
+/// Returns a detached, recursive copy of the mount tree at `fd`.
+pub(super) fn open_tree(fd: BorrowedFd<'_>) -> nix::Result<OwnedFd> {
+    open_tree_raw(fd.as_raw_fd(), "", libc::AT_EMPTY_PATH as libc::c_uint)
+}
+
+/// Returns a detached, recursive copy of the mount tree at `path`.
+pub(super) fn open_tree_at<P: ?Sized + NixPath>(path: &P) -> nix::Result<OwnedFd> {
+    open_tree_raw(libc::AT_FDCWD, path, 0)
+}
+
+fn open_tree_raw<P: ?Sized + NixPath>(
+    dirfd: RawFd,
+    path: &P,
+    flags: libc::c_uint,
+) -> nix::Result<OwnedFd> {
+    let flags = flags
+        | libc::OPEN_TREE_CLONE
+        | libc::OPEN_TREE_CLOEXEC
+        | libc::AT_RECURSIVE as libc::c_uint;
+
+    let res = path.with_nix_path(|cstr| unsafe {
+        libc::syscall(libc::SYS_open_tree, dirfd, cstr.as_ptr(), flags)
+    })?;
+
+    // SAFETY: on success the syscall returns a new fd that nothing else owns
+    Errno::result(res).map(|fd| unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+}
+
+/// Attaches the detached tree at `from` onto the mountpoint referred to by `to`.
+pub(super) fn move_mount(from: BorrowedFd<'_>, to: BorrowedFd<'_>) -> nix::Result<()> {
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_move_mount,
+            from.as_raw_fd(),
+            c"".as_ptr(),
+            to.as_raw_fd(),
+            c"".as_ptr(),
+            libc::MOVE_MOUNT_F_EMPTY_PATH | libc::MOVE_MOUNT_T_EMPTY_PATH,
+        )
+    };
+
+    Errno::result(res).map(drop)
+}
@@ -0,0 +1,110 @@
+use super::{attr::MountAttr, bind::move_mount};
+use nix::{errno::Errno, libc};
+use std::{
+    ffi::CString,
+    os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd},
+};
+
+// Not exported by libc yet, see 'include/uapi/linux/mount.h'
+const FSOPEN_CLOEXEC: libc::c_uint = 0x1;
+const FSMOUNT_CLOEXEC: libc::c_uint = 0x1;
+const FSCONFIG_SET_FLAG: libc::c_uint = 0;
+const FSCONFIG_SET_STRING: libc::c_uint = 1;
+const FSCONFIG_CMD_CREATE: libc::c_uint = 6;
+
+/// Fresh filesystem instance, created detached and attached to a target fd.
+///
+/// Unlike `mount(2)` nothing is resolved by path, the caller decides where it lands.
+#[derive(Debug, Clone)]
+pub struct FsMount<'a> {
+    fstype: &'a str,
+    options: Vec<(&'a str, Option<String>)>,
+    attrs: MountAttr,
+}
+
+impl<'a> FsMount<'a> {
+    pub fn new(fstype: &'a str) -> Self {
+        Self {
+            fstype,
+            options: Vec::new(),
+            attrs: MountAttr::empty(),
+        }
+    }
+
+    /// `key=value` filesystem option.
+    pub fn option(mut self, key: &'a str, value: impl Into<String>) -> Self {
+        self.options.push((key, Some(value.into())));
+        self
+    }
+
+    /// Valueless filesystem option, e.g. `newinstance`.
+    pub fn flag(mut self, key: &'a str) -> Self {
+        self.options.push((key, None));
+        self
+    }
+
+    /// Mount (not superblock) attributes, set before the mount becomes visible.
+    pub fn attrs(mut self, attrs: MountAttr) -> Self {
+        self.attrs |= attrs;
+        self
+    }
+
+    pub fn mount_at(&self, target: BorrowedFd<'_>) -> nix::Result<()> {
+        let mnt = self.create()?;
+        move_mount(mnt.as_fd(), target)
+    }
+
+    /// Returns the detached mount.
+    pub fn create(&self) -> nix::Result<OwnedFd> {
+        let fstype = cstring(self.fstype)?;
+        let res = unsafe { libc::syscall(libc::SYS_fsopen, fstype.as_ptr(), FSOPEN_CLOEXEC) };
+        // SAFETY: on success the syscall returns a new fd that nothing else owns
+        let fs = Errno::result(res).map(|fd| unsafe { OwnedFd::from_raw_fd(fd as RawFd) })?;
+
+        fsconfig(&fs, FSCONFIG_SET_STRING, Some("source"), Some(self.fstype))?;
+        for (key, value) in &self.options {
+            match value {
+                Some(value) => fsconfig(&fs, FSCONFIG_SET_STRING, Some(key), Some(value))?,
+                None => fsconfig(&fs, FSCONFIG_SET_FLAG, Some(key), None)?,
+            }
+        }
+        fsconfig(&fs, FSCONFIG_CMD_CREATE, None, None)?;
+
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_fsmount,
+                fs.as_raw_fd(),
+                FSMOUNT_CLOEXEC,
+                self.attrs.bits() as libc::c_uint,
+            )
+        };
+        // SAFETY: same as above
+        Errno::result(res).map(|fd| unsafe { OwnedFd::from_raw_fd(fd as RawFd) })
+    }
+}
+
+fn fsconfig(
+    fs: &OwnedFd,
+    cmd: libc::c_uint,
+    key: Option<&str>,
+    value: Option<&str>,
+) -> nix::Result<()> {
+    let key = key.map(cstring).transpose()?;
+    let value = value.map(cstring).transpose()?;
+
+    let res = unsafe {
+        libc::syscall(
+            libc::SYS_fsconfig,
+            fs.as_raw_fd(),
+            cmd,
+            key.as_ref().map_or(std::ptr::null(), |k| k.as_ptr()),
+            value.as_ref().map_or(std::ptr::null(), |v| v.as_ptr()),
+            0,
+        )
+    };
+    Errno::result(res).map(drop)
+}
+
+fn cstring(s: &str) -> nix::Result<CString> {
+    CString::new(s).map_err(|_| Errno::EINVAL)
+}
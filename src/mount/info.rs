@@ -5,21 +5,27 @@ use nix::{
     sys::statfs::{PROC_SUPER_MAGIC, fstatfs},
 };
 use std::{
+    ffi::OsString,
+    fmt,
     fs::File,
-    io::{BufRead, BufReader, Lines},
-    os::fd::BorrowedFd,
+    io::{BufRead, BufReader, Read, Split},
+    os::{fd::BorrowedFd, unix::ffi::OsStringExt},
     path::PathBuf,
     str::FromStr,
 };
 use thiserror::Error;
 
+// Not exported by libc yet, see 'include/uapi/linux/mount.h'
+const MS_NOSYMFOLLOW: u32 = 256;
+
+/// Reader over a mountinfo table, usually '/proc/self/mountinfo'.
 #[derive(Debug)]
-pub(crate) struct MountInfo {
-    reader: BufReader<File>,
+pub struct MountInfo<R = File> {
+    reader: BufReader<R>,
 }
 
 #[derive(Debug, Error)]
-pub(crate) enum MountInfoError {
+pub enum MountInfoError {
     #[error("file descriptor is not a procfs mount")]
     InvalidProcFd,
 
@@ -55,49 +61,84 @@ impl TryFrom<BorrowedFd<'_>> for MountInfo {
         )
         .map_err(|e| MountInfoError::Open(std::io::Error::from(e)))?;
 
-        Ok(MountInfo {
-            reader: BufReader::new(File::from(fd)),
-        })
+        Ok(MountInfo::from_reader(File::from(fd)))
     }
 }
 
-pub(crate) struct MountInfoIter {
-    lines: Lines<BufReader<File>>,
+impl<R: Read> MountInfo<R> {
+    /// Table in the mountinfo format from any source, e.g. a captured copy.
+    pub fn from_reader(reader: R) -> Self {
+        Self {
+            reader: BufReader::new(reader),
+        }
+    }
 }
 
-impl Iterator for MountInfoIter {
+pub struct MountInfoIter<R = File> {
+    // Paths aren't necessarily UTF-8, the kernel only escapes whitespace and '\'
+    lines: Split<BufReader<R>>,
+}
+
+impl<R: Read> Iterator for MountInfoIter<R> {
     type Item = Result<MountLine, MountInfoError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         self.lines.next().map(|line| {
-            line.map_err(MountInfoError::Read)?
-                .parse::<MountLine>()
-                .map_err(MountInfoError::Parse)
+            MountLine::parse(&line.map_err(MountInfoError::Read)?).map_err(MountInfoError::Parse)
         })
     }
 }
 
-impl IntoIterator for MountInfo {
+impl<R: Read> IntoIterator for MountInfo<R> {
     type Item = Result<MountLine, MountInfoError>;
-    type IntoIter = MountInfoIter;
+    type IntoIter = MountInfoIter<R>;
 
     fn into_iter(self) -> Self::IntoIter {
         MountInfoIter {
-            lines: self.reader.lines(),
+            lines: self.reader.split(b'\n'),
         }
     }
 }
 
-#[derive(Debug)]
-pub(crate) struct MountLine {
-    id: u32,
-    parent_id: u32,
-    mountpoint: PathBuf,
-    options: MountFlags,
+/// A single mount, i.e. one line of mountinfo.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MountLine {
+    pub id: u32,
+    pub parent_id: u32,
+    pub major: u32,
+    pub minor: u32,
+
+    /// Directory of the filesystem that forms the root of this mount, '/' unless it's a bind.
+    pub root: PathBuf,
+    pub mountpoint: PathBuf,
+
+    /// Per-mount options.
+    pub options: MountFlags,
+    /// Per-mount options w/o a flag, e.g. `idmapped`.
+    pub extra_options: Vec<String>,
+
+    pub propagation: Vec<PropagationTag>,
+
+    pub fstype: String,
+    pub source: String,
+    /// Per-superblock options, as the filesystem reports them.
+    pub super_options: Vec<String>,
+}
+
+/// Optional fields before the '-' separator, unknown ones are skipped as the format asks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PropagationTag {
+    /// `shared:<peer group>`
+    Shared(u32),
+    /// `master:<peer group>`, the mount is a slave of that group.
+    Master(u32),
+    /// `propagate_from:<peer group>`, closest dominant group the slave receives from.
+    PropagateFrom(u32),
+    Unbindable,
 }
 
 #[derive(Debug, Error)]
-pub(crate) enum MountLineError {
+pub enum MountLineError {
     #[error("missing or malformed field `{field}`")]
     Malformed { field: &'static str },
 
@@ -107,53 +148,122 @@ pub(crate) enum MountLineError {
 
 #[derive(Debug, Error)]
 #[error("unrecognized flag `{0}`")]
-pub(crate) struct ParseFlagsError(String);
+pub struct ParseFlagsError(String);
+
+impl MountLine {
+    /// Peer group of a shared mount.
+    pub fn peer_group(&self) -> Option<u32> {
+        self.propagation.iter().find_map(|tag| match tag {
+            PropagationTag::Shared(id) => Some(*id),
+            _ => None,
+        })
+    }
 
-// Valid format: <https://manpages.ubuntu.com/manpages/noble/man5/proc_pid_mountinfo.5.html>
-impl FromStr for MountLine {
-    type Err = MountLineError;
+    /// Peer group a slave mount receives from.
+    pub fn master(&self) -> Option<u32> {
+        self.propagation.iter().find_map(|tag| match tag {
+            PropagationTag::Master(id) => Some(*id),
+            _ => None,
+        })
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let mut parts = s.split_whitespace();
+    pub fn is_unbindable(&self) -> bool {
+        self.propagation.contains(&PropagationTag::Unbindable)
+    }
+
+    // Valid format: <https://manpages.ubuntu.com/manpages/noble/man5/proc_pid_mountinfo.5.html>
+    pub fn parse(line: &[u8]) -> Result<Self, MountLineError> {
+        let mut parts = line
+            .strip_suffix(b"\n")
+            .unwrap_or(line)
+            .split(|&b| b == b' ');
         let mut next =
             |field: &'static str| parts.next().ok_or(MountLineError::Malformed { field });
 
-        let id = next("mount ID")?
-            .parse::<u32>()
-            .map_err(|_| MountLineError::Malformed { field: "mount ID" })?;
-        let parent_id = next("parent ID")?
-            .parse::<u32>()
-            .map_err(|_| MountLineError::Malformed { field: "parent ID" })?;
-
-        // skip 'major:minor' and 'root' fields
-        {
-            let _major_minor = next("major:minor")?;
-            let _root = next("root")?;
+        let number = |field: &'static str, raw: &str| {
+            raw.parse::<u32>()
+                .map_err(|_| MountLineError::Malformed { field })
+        };
+
+        let id = number("mount ID", &lossy(next("mount ID")?))?;
+        let parent_id = number("parent ID", &lossy(next("parent ID")?))?;
+
+        let major_minor = lossy(next("major:minor")?);
+        let (major, minor) = major_minor
+            .split_once(':')
+            .ok_or(MountLineError::Malformed {
+                field: "major:minor",
+            })?;
+        let (major, minor) = (number("major", major)?, number("minor", minor)?);
+
+        let root = unescape_path(next("root")?);
+        let mountpoint = unescape_path(next("mountpoint")?);
+
+        let (options, extra_options) = parse_mount_options(&lossy(next("mount options")?))
+            .map_err(MountLineError::InvalidFlags)?;
+
+        // Zero or more optional fields, terminated by a single '-'
+        let mut propagation = Vec::new();
+        loop {
+            let tag = lossy(next("separator")?);
+            let parsed = match tag.split_once(':') {
+                _ if tag == "-" => break,
+                _ if tag == "unbindable" => Some(PropagationTag::Unbindable),
+                Some(("shared", id)) => Some(PropagationTag::Shared(number("shared", id)?)),
+                Some(("master", id)) => Some(PropagationTag::Master(number("master", id)?)),
+                Some(("propagate_from", id)) => {
+                    Some(PropagationTag::PropagateFrom(number("propagate_from", id)?))
+                }
+                _ => None,
+            };
+            propagation.extend(parsed);
         }
 
-        let mountpoint = PathBuf::from(next("mountpoint")?);
-        let options =
-            MountFlags::from_str(next("mount options")?).map_err(MountLineError::InvalidFlags)?;
+        let fstype = lossy(&unescape(next("filesystem type")?));
+        let source = lossy(&unescape(next("mount source")?));
+        let super_options = lossy(next("super options")?)
+            .split(',')
+            .filter(|opt| !opt.is_empty())
+            .map(|opt| lossy(&unescape(opt.as_bytes())))
+            .collect();
 
         Ok(MountLine {
             id,
             parent_id,
+            major,
+            minor,
+            root,
             mountpoint,
             options,
+            extra_options,
+            propagation,
+            fstype,
+            source,
+            super_options,
         })
     }
 }
 
+impl FromStr for MountLine {
+    type Err = MountLineError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s.as_bytes())
+    }
+}
+
 bitflags::bitflags! {
-   #[derive(Clone, Copy, Debug, PartialEq, Eq)]
-    struct MountFlags: u32 {
-        const RDONLY     = MS_RDONLY as u32;
-        const NOSUID     = MS_NOSUID as u32;
-        const NODEV      = MS_NODEV as u32;
-        const NOEXEC     = MS_NOEXEC as u32;
-        const NOATIME    = MS_NOATIME as u32;
-        const NODIRATIME = MS_NODIRATIME as u32;
-        const RELATIME   = MS_RELATIME as u32;
+    /// Per-mount flags as listed in mountinfo, `rw` and `strictatime` have none.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    pub struct MountFlags: u32 {
+        const RDONLY      = MS_RDONLY as u32;
+        const NOSUID      = MS_NOSUID as u32;
+        const NODEV       = MS_NODEV as u32;
+        const NOEXEC      = MS_NOEXEC as u32;
+        const NOATIME     = MS_NOATIME as u32;
+        const NODIRATIME  = MS_NODIRATIME as u32;
+        const RELATIME    = MS_RELATIME as u32;
+        const NOSYMFOLLOW = MS_NOSYMFOLLOW;
     }
 }
 
@@ -161,35 +271,140 @@ impl FromStr for MountFlags {
     type Err = ParseFlagsError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let flags = s
-            .trim()
+        s.trim()
             .split(',')
-            .filter_map(|flag| match flag {
-                "ro" => Some(MountFlags::RDONLY),
-                "nosuid" => Some(MountFlags::NOSUID),
-                "nodev" => Some(MountFlags::NODEV),
-                "noexec" => Some(MountFlags::NOEXEC),
-                "noatime" => Some(MountFlags::NOATIME),
-                "nodiratime" => Some(MountFlags::NODIRATIME),
-                "relatime" => Some(MountFlags::RELATIME),
-                _ => None,
+            .filter(|flag| !flag.is_empty())
+            .try_fold(MountFlags::empty(), |acc, flag| {
+                mount_flag(flag)
+                    .map(|flag| acc | flag)
+                    .ok_or_else(|| ParseFlagsError(flag.to_owned()))
             })
-            .fold(MountFlags::empty(), |acc, flag| acc | flag);
-        Ok(flags)
     }
 }
 
+/// Back to mountinfo's spelling, e.g. `ro,nosuid,relatime`.
+impl fmt::Display for MountFlags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let access = match self.contains(MountFlags::RDONLY) {
+            true => "ro",
+            false => "rw",
+        };
+        write!(f, "{access}")?;
+
+        for (name, _) in self.difference(MountFlags::RDONLY).iter_names() {
+            write!(f, ",{}", name.to_ascii_lowercase())?;
+        }
+        Ok(())
+    }
+}
+
+/// `None` for an option that doesn't map to a flag.
+fn mount_flag(flag: &str) -> Option<MountFlags> {
+    match flag {
+        "ro" => Some(MountFlags::RDONLY),
+        "rw" => Some(MountFlags::empty()),
+        "nosuid" => Some(MountFlags::NOSUID),
+        "nodev" => Some(MountFlags::NODEV),
+        "noexec" => Some(MountFlags::NOEXEC),
+        "noatime" => Some(MountFlags::NOATIME),
+        "nodiratime" => Some(MountFlags::NODIRATIME),
+        "relatime" => Some(MountFlags::RELATIME),
+        "nosymfollow" => Some(MountFlags::NOSYMFOLLOW),
+        _ => None,
+    }
+}
+
+/// Splits per-mount options into flags and the rest, newer kernels may add options.
+fn parse_mount_options(s: &str) -> Result<(MountFlags, Vec<String>), ParseFlagsError> {
+    let mut flags = MountFlags::empty();
+    let mut extra = Vec::new();
+
+    for opt in s.split(',') {
+        match (opt, mount_flag(opt)) {
+            ("", _) => return Err(ParseFlagsError(s.to_owned())),
+            (_, Some(flag)) => flags |= flag,
+            (opt, None) => extra.push(opt.to_owned()),
+        }
+    }
+    Ok((flags, extra))
+}
+
+/// Undoes the kernel's octal escapes, e.g. '\040' for a space.
+fn unescape(raw: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(raw.len());
+    let mut idx = 0;
+
+    while idx < raw.len() {
+        let octal = raw
+            .get(idx + 1..idx + 4)
+            .filter(|digits| raw[idx] == b'\\' && digits.iter().all(|d| (b'0'..=b'7').contains(d)))
+            .map(|digits| {
+                digits
+                    .iter()
+                    .fold(0u32, |acc, d| acc * 8 + u32::from(d - b'0'))
+            })
+            .and_then(|value| u8::try_from(value).ok());
+
+        match octal {
+            Some(byte) => {
+                out.push(byte);
+                idx += 4;
+            }
+            None => {
+                out.push(raw[idx]);
+                idx += 1;
+            }
+        }
+    }
+    out
+}
+
+fn unescape_path(raw: &[u8]) -> PathBuf {
+    PathBuf::from(OsString::from_vec(unescape(raw)))
+}
+
+fn lossy(raw: &[u8]) -> String {
+    String::from_utf8_lossy(raw).into_owned()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::os::fd::AsFd;
 
+    // Captured inside the sandbox: a shared tmpfs, a bind w/ spaces and an overlay
+    const SANDBOX_FIXTURE: &str = r"65 43 0:39 /newroot / rw,nosuid,nodev,relatime - tmpfs tmpfs rw
+66 65 254:0 /usr /usr ro,nosuid,nodev,relatime - ext4 /dev/vda rw,discard,resv_strict,resuid=65534,resgid=65534
+67 65 0:40 / /proc rw,nosuid,nodev,noexec,relatime - proc proc rw
+68 67 0:40 /sys /proc/sys ro,nosuid,nodev,noexec,relatime - proc proc rw
+73 67 0:6 /null /proc/keys ro,nosuid,relatime - devtmpfs devtmpfs rw,size=3072116k,nr_inodes=768029,mode=755
+75 65 0:42 / /tmp rw,nosuid,nodev,relatime,nosymfollow shared:1 - tmpfs tmpfs rw,mode=755
+76 65 254:0 /tmp/idt/with\040space /data\040dir rw,nosuid,nodev,noexec,relatime - ext4 /dev/vda rw,discard,resv_strict,resuid=65534,resgid=65534
+80 65 0:44 / /ovl rw,nosuid,nodev,relatime - overlay overlay rw,lowerdir=/oldroot/tmp/idt/ovl/lower,upperdir=/overlay-0/upper,workdir=/overlay-0/work,redirect_dir=nofollow,uuid=on,userxattr
+";
+
+    // Captured in a slave mount namespace of a systemd host
+    const SLAVE_FIXTURE: &str = r"512 489 259:2 / / rw,relatime master:1 - ext4 /dev/nvme0n1p2 rw
+513 512 0:5 / /dev rw,nosuid shared:7 master:2 propagate_from:2 - devtmpfs udev rw,size=8012344k,mode=755,inode64
+514 512 0:62 / /mnt/private rw,relatime unbindable - tmpfs none rw,inode64
+515 512 259:2 /srv/cache /cache rw,relatime,idmapped master:1 - ext4 /dev/nvme0n1p2 rw
+516 512 0:63 / /media/usb\134stick rw,nosuid,nodev,relatime opt:4 - vfat /dev/sdb1 rw,uid=1000,iocharset=utf8,shortname=mixed,errors=remount-ro
+517 512 0:64 / /run/user/1000/doc rw,nosuid,nodev,relatime - fuse.portal portal rw,user_id=1000,group_id=1000
+";
+
     fn create_mountinfo(path: impl AsRef<std::path::Path>) -> Result<MountInfo, MountInfoError> {
         let proc_fd = nix::fcntl::open(path.as_ref(), OFlag::O_PATH, Mode::empty())
             .map_err(|e| MountInfoError::ValidateProcFd(std::io::Error::from(e)))?;
         MountInfo::try_from(proc_fd.as_fd())
     }
 
+    fn parse_fixture(fixture: &str) -> Vec<MountLine> {
+        MountInfo::from_reader(fixture.as_bytes())
+            .into_iter()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
     #[test]
     fn test_mountinfo_invalid_procfd() {
         assert!(matches!(
@@ -208,4 +423,90 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_parse_sandbox_fixture() {
+        let lines = parse_fixture(SANDBOX_FIXTURE);
+        assert_eq!(lines.len(), 8);
+
+        let root = &lines[0];
+        assert_eq!((root.id, root.parent_id), (65, 43));
+        assert_eq!((root.major, root.minor), (0, 39));
+        assert_eq!(root.root, PathBuf::from("/newroot"));
+        assert_eq!(root.fstype, "tmpfs");
+        assert!(root.propagation.is_empty());
+
+        let usr = &lines[1];
+        assert!(usr.options.contains(MountFlags::RDONLY | MountFlags::NODEV));
+        assert_eq!(usr.source, "/dev/vda");
+        assert_eq!(usr.super_options[0], "rw");
+        assert_eq!(usr.super_options.len(), 5);
+
+        let tmp = &lines[5];
+        assert!(tmp.options.contains(MountFlags::NOSYMFOLLOW));
+        assert_eq!(tmp.peer_group(), Some(1));
+
+        let data = &lines[6];
+        assert_eq!(data.root, PathBuf::from("/tmp/idt/with space"));
+        assert_eq!(data.mountpoint, PathBuf::from("/data dir"));
+
+        let overlay = &lines[7];
+        assert_eq!(overlay.fstype, "overlay");
+        assert!(overlay.super_options.contains(&"userxattr".to_owned()));
+    }
+
+    #[test]
+    fn test_parse_slave_fixture() {
+        let lines = parse_fixture(SLAVE_FIXTURE);
+
+        assert_eq!(lines[0].master(), Some(1));
+        assert_eq!(lines[0].peer_group(), None);
+        assert_eq!(
+            lines[1].propagation,
+            [
+                PropagationTag::Shared(7),
+                PropagationTag::Master(2),
+                PropagationTag::PropagateFrom(2)
+            ]
+        );
+        assert_eq!(lines[1].source, "udev");
+        assert!(lines[2].is_unbindable());
+        assert_eq!(lines[3].extra_options, ["idmapped"]);
+        assert_eq!(lines[4].mountpoint, PathBuf::from(r"/media/usb\stick"));
+        // Unknown optional fields are skipped
+        assert!(lines[4].propagation.is_empty());
+        assert_eq!(lines[5].fstype, "fuse.portal");
+    }
+
+    #[test]
+    fn test_parse_rejects_malformed() {
+        assert!("65 43 0:39 / /".parse::<MountLine>().is_err());
+        assert!(
+            "x 43 0:39 / / rw - tmpfs tmpfs rw"
+                .parse::<MountLine>()
+                .is_err()
+        );
+        assert!(
+            "65 43 039 / / rw - tmpfs tmpfs rw"
+                .parse::<MountLine>()
+                .is_err()
+        );
+        assert!(
+            "65 43 0:39 / / rw shared:x - tmpfs tmpfs rw"
+                .parse::<MountLine>()
+                .is_err()
+        );
+        assert!(
+            "65 43 0:39 / / rw shared:1 tmpfs tmpfs rw"
+                .parse::<MountLine>()
+                .is_err()
+        );
+
+        assert_eq!(
+            "ro,nosuid,nosymfollow".parse::<MountFlags>().unwrap(),
+            MountFlags::RDONLY | MountFlags::NOSUID | MountFlags::NOSYMFOLLOW
+        );
+        assert!("ro,bogus".parse::<MountFlags>().is_err());
+        assert_eq!(unescape(br"a\040b\011c\134\12"), b"a b\tc\\\\12");
+    }
 }
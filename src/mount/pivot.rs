@@ -5,6 +5,7 @@ use nix::{
 use std::{
     ffi::OsStr,
     fs::DirBuilder,
+    io::ErrorKind,
     marker::PhantomData,
     os::{fd::OwnedFd, unix::fs::DirBuilderExt},
     path::{Component, Path, PathBuf},
@@ -16,12 +17,24 @@ mod sealed {
 
 #[derive(Debug, thiserror::Error)]
 pub enum PivotError {
-    #[error("base path is not a directory")]
-    NotADirectory,
+    #[error("staging directory '{}' does not exist", .0.display())]
+    NotFound(PathBuf),
+
+    #[error("staging directory '{}' is not a directory", .0.display())]
+    NotADirectory(PathBuf),
+
+    #[error("staging directory '{}' must be absolute and can't be '/'", .0.display())]
+    InvalidBase(PathBuf),
 
     #[error("child path escapes base: path traversal rejected")]
     PathTraversal,
 
+    #[error("root name '{0}' must be a single path component")]
+    InvalidName(String),
+
+    #[error("new and old root can't both be named '{0}'")]
+    NameClash(String),
+
     #[error("mount operation failed: {stage}")]
     Mount {
         stage: &'static str,
@@ -63,32 +76,55 @@ pub struct PivotContext<State: sealed::Sealed> {
 }
 
 impl PivotContext<Uninitialized> {
-    // TODO: Check for path existance and nesting (w/ naming).
+    /// `base_path` is resolved up front, the pivots rely on it being canonical.
     pub fn new(
         base_path: impl AsRef<Path>,
         new_root_name: impl AsRef<OsStr>,
         old_root_name: impl AsRef<OsStr>,
     ) -> Result<Self, PivotError> {
         let base_path = base_path.as_ref();
-
-        if !base_path.is_dir() {
-            return Err(PivotError::NotADirectory);
+        if !base_path.is_absolute() {
+            return Err(PivotError::InvalidBase(base_path.to_path_buf()));
         }
 
-        for name in [new_root_name.as_ref(), old_root_name.as_ref()] {
-            for component in Path::new(name).components() {
-                if component == Component::ParentDir {
-                    return Err(PivotError::PathTraversal);
-                }
+        let base_path = match base_path.canonicalize() {
+            Ok(path) if path == Path::new("/") => return Err(PivotError::InvalidBase(path)),
+            Ok(path) if !path.is_dir() => return Err(PivotError::NotADirectory(path)),
+            Ok(path) => path,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(PivotError::NotFound(base_path.to_path_buf()));
+            }
+            Err(e) => {
+                return Err(PivotError::Fs {
+                    stage: "resolve <base-path>",
+                    source: e,
+                });
+            }
+        };
+
+        let (new_root_name, old_root_name) = (new_root_name.as_ref(), old_root_name.as_ref());
+        for name in [new_root_name, old_root_name] {
+            let mut components = Path::new(name).components();
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(_)), None) => {}
+                (Some(Component::ParentDir), None) => return Err(PivotError::PathTraversal),
+                _ => return Err(PivotError::InvalidName(name.to_string_lossy().into_owned())),
             }
         }
 
-        return Ok(Self {
-            base_path: base_path.to_path_buf(),
-            old_root: base_path.join(old_root_name.as_ref()),
-            new_root: base_path.join(new_root_name.as_ref()),
+        // Single components, so they can only nest by being the same
+        if new_root_name == old_root_name {
+            return Err(PivotError::NameClash(
+                new_root_name.to_string_lossy().into_owned(),
+            ));
+        }
+
+        Ok(Self {
+            old_root: base_path.join(old_root_name),
+            new_root: base_path.join(new_root_name),
+            base_path,
             _state: PhantomData,
-        });
+        })
     }
 }
 
@@ -101,7 +137,7 @@ impl PivotContext<Uninitialized> {
                 source: e,
             })?;
 
-        // Mount tmpfs at '/tmp'
+        // Mount tmpfs at '<base-path>'
         mount::<str, Path, str, str>(
             Some("tmpfs"),
             &self.base_path,
@@ -125,13 +161,13 @@ impl PivotContext<Uninitialized> {
 
 impl PivotContext<StagingMounted> {
     pub fn bind_new_root(self) -> Result<PivotContext<RootMounted>, PivotError> {
-        // We're in '/tmp'
+        // We're in '<base-path>'
         chdir(&self.base_path).map_err(|e| PivotError::Fs {
             stage: "chdir (into base-path>)",
             source: e.into(),
         })?;
 
-        // We've '/tmp/<new-root>'
+        // We've '<base-path>/<new-root>'
         DirBuilder::new()
             .mode(0o755)
             .create(&self.new_root)
@@ -153,7 +189,7 @@ impl PivotContext<StagingMounted> {
             source: e,
         })?;
 
-        // We have '/tmp/<old-root>'
+        // We have '<base-path>/<old-root>'
         DirBuilder::new()
             .mode(0o755)
             .create(&self.old_root)
@@ -180,16 +216,21 @@ impl PivotContext<RootMounted> {
         })?;
 
         // Change the working directory to the new root ('/'),
-        // which should now be 'BASE_PATH'.
+        // which should now be '<base-path>'.
         chdir("/").map_err(|e| PivotError::Fs {
             stage: "chdir (into /<base-path>)",
             source: e.into(),
         })?;
 
+        // '<base-path>/<name>' is now reachable as '/<name>'
+        let rebase = |path: &Path| {
+            PathBuf::from("/").join(path.strip_prefix(&self.base_path).unwrap_or(path))
+        };
+
         Ok(PivotContext {
             base_path: PathBuf::from("/"),
-            old_root: PathBuf::from("/").join(&self.old_root),
-            new_root: PathBuf::from("/").join(&self.new_root),
+            old_root: rebase(&self.old_root),
+            new_root: rebase(&self.new_root),
             _state: PhantomData,
         })
     }
@@ -295,3 +336,51 @@ impl PivotContext<PivotedToNewRoot> {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_new_validates_staging() {
+        let base = std::env::temp_dir().join(format!("enclosure-pivot-{}", std::process::id()));
+        std::fs::create_dir_all(&base).unwrap();
+        std::fs::write(base.join("file"), "").unwrap();
+        std::os::unix::fs::symlink(&base, base.join("link")).unwrap();
+
+        let ctx = PivotContext::new(base.join("link"), "newroot", "oldroot").unwrap();
+        assert_eq!(ctx.new_root, base.join("newroot"));
+
+        let err = |base: &Path, new: &str, old: &str| PivotContext::new(base, new, old).err();
+        assert!(matches!(
+            err(&base.join("missing"), "n", "o"),
+            Some(PivotError::NotFound(_))
+        ));
+        assert!(matches!(
+            err(&base.join("file"), "n", "o"),
+            Some(PivotError::NotADirectory(_))
+        ));
+        assert!(matches!(
+            err(Path::new("tmp"), "n", "o"),
+            Some(PivotError::InvalidBase(_))
+        ));
+        assert!(matches!(
+            err(Path::new("/"), "n", "o"),
+            Some(PivotError::InvalidBase(_))
+        ));
+        assert!(matches!(
+            err(&base, "..", "o"),
+            Some(PivotError::PathTraversal)
+        ));
+        assert!(matches!(
+            err(&base, "a/b", "o"),
+            Some(PivotError::InvalidName(_))
+        ));
+        assert!(matches!(
+            err(&base, "root", "root"),
+            Some(PivotError::NameClash(_))
+        ));
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,253 @@
+use crate::config::{MountEntry, MountSpec};
+use std::path::{Component, Path, PathBuf};
+
+#[derive(Debug, thiserror::Error)]
+pub enum PlanError {
+    #[error("{kind} destination '{}' must be absolute", .dest.display())]
+    RelativeDestination { kind: &'static str, dest: PathBuf },
+
+    #[error("{kind} destination '{}' cannot contain '..'", .dest.display())]
+    ParentComponent { kind: &'static str, dest: PathBuf },
+
+    #[error("{kind} destination can't be '/', the new root is opened once and would be hidden")]
+    RootDestination { kind: &'static str },
+
+    #[error("'{}' is the destination of both a {first} and a {second} entry", .dest.display())]
+    DuplicateTarget {
+        dest: PathBuf,
+        first: &'static str,
+        second: &'static str,
+    },
+}
+
+/// Mount entries checked and ordered by the parent, the child applies them as is.
+///
+/// Entries are stable-sorted by phase, then by depth, so a mount never hides one that
+/// was meant to sit below it:
+/// 1. everything that creates a path or a mount, parents before children
+/// 2. `remount`, which freezes what's below
+///
+/// A `chmod` stays right after the entries listed before it that lead to its path, so a
+/// mount listed after it still covers the changed mode.
+#[derive(Debug)]
+pub struct MountPlan {
+    specs: Vec<MountSpec>,
+}
+
+impl MountPlan {
+    pub fn new(specs: &[MountSpec]) -> Result<Self, PlanError> {
+        let mut specs = specs.to_vec();
+
+        for spec in &mut specs {
+            let kind = spec.entry.kind();
+            let dest = spec.entry.dest_mut();
+            *dest = normalize(kind, dest)?;
+
+            if occupies(&spec.entry) && spec.entry.dest().parent().is_none() {
+                return Err(PlanError::RootDestination { kind });
+            }
+        }
+
+        check_duplicates(&specs)?;
+        warn_shadowed(&specs);
+
+        let (chmods, mut ordered): (Vec<_>, Vec<_>) = specs
+            .into_iter()
+            .enumerate()
+            .partition(|(_, spec)| matches!(spec.entry, MountEntry::Chmod(_)));
+        ordered.sort_by_key(|(_, spec)| (phase(&spec.entry), depth(spec.entry.dest())));
+
+        for (listed, chmod) in chmods {
+            let path = chmod.entry.dest();
+            let after = ordered.iter().rposition(|(idx, spec)| {
+                *idx < listed && phase(&spec.entry) == 0 && path.starts_with(spec.entry.dest())
+            });
+            ordered.insert(after.map_or(0, |pos| pos + 1), (listed, chmod));
+        }
+
+        Ok(Self {
+            specs: ordered.into_iter().map(|(_, spec)| spec).collect(),
+        })
+    }
+
+    pub fn into_specs(self) -> Vec<MountSpec> {
+        self.specs
+    }
+}
+
+/// Absolute, w/o '.', trailing or repeated separators.
+fn normalize(kind: &'static str, dest: &Path) -> Result<PathBuf, PlanError> {
+    if !dest.is_absolute() {
+        return Err(PlanError::RelativeDestination {
+            kind,
+            dest: dest.to_path_buf(),
+        });
+    }
+
+    // '..' could only be resolved against the new root, which doesn't exist yet
+    if dest.components().any(|c| c == Component::ParentDir) {
+        return Err(PlanError::ParentComponent {
+            kind,
+            dest: dest.to_path_buf(),
+        });
+    }
+
+    // `components()` already drops '.' and redundant separators
+    Ok(dest.components().collect())
+}
+
+fn phase(entry: &MountEntry) -> u8 {
+    match entry {
+        MountEntry::Remount { .. } => 1,
+        _ => 0,
+    }
+}
+
+fn depth(dest: &Path) -> usize {
+    dest.components().count()
+}
+
+/// Whether the entry takes over `dest()`, a second one there would hide it.
+fn occupies(entry: &MountEntry) -> bool {
+    match entry {
+        MountEntry::File { .. } | MountEntry::Symlink { .. } => true,
+        MountEntry::Remount { .. } => false,
+        entry => entry.creates_mount(),
+    }
+}
+
+fn check_duplicates(specs: &[MountSpec]) -> Result<(), PlanError> {
+    let occupied = specs.iter().filter(|spec| occupies(&spec.entry));
+
+    for (idx, first) in occupied.clone().enumerate() {
+        let dest = first.entry.dest();
+
+        if let Some(second) = occupied
+            .clone()
+            .skip(idx + 1)
+            .find(|spec| spec.entry.dest() == dest)
+        {
+            return Err(PlanError::DuplicateTarget {
+                dest: dest.to_path_buf(),
+                first: first.entry.kind(),
+                second: second.entry.kind(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Reports entries the CLI order would have hidden below a later mount, sorting fixes them
+/// for all but `chmod`, which keeps its place.
+fn warn_shadowed(specs: &[MountSpec]) {
+    for (idx, spec) in specs.iter().enumerate() {
+        let dest = spec.entry.dest();
+
+        for later in &specs[idx + 1..] {
+            let parent = later.entry.dest();
+            if phase(&later.entry) == 0
+                && later.entry.creates_mount()
+                && dest != parent
+                && dest.starts_with(parent)
+            {
+                if let MountEntry::Chmod(_) = spec.entry {
+                    println!(
+                        "[PARENT]: chmod of '{}' is listed before the {} mount at '{}', which hides it",
+                        dest.display(),
+                        later.entry.kind(),
+                        parent.display()
+                    );
+                    continue;
+                }
+
+                println!(
+                    "[PARENT]: '{}' is listed before the {} mount at '{}', mounting that first",
+                    dest.display(),
+                    later.entry.kind(),
+                    parent.display()
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plan(specs: &[&str]) -> Result<Vec<PathBuf>, PlanError> {
+        let specs: Vec<MountSpec> = specs.iter().map(|s| s.parse().unwrap()).collect();
+        Ok(MountPlan::new(&specs)?
+            .into_specs()
+            .iter()
+            .map(|spec| spec.entry.dest().to_path_buf())
+            .collect())
+    }
+
+    #[test]
+    fn test_plan_orders_parents_first() {
+        let order = plan(&[
+            "remount:/usr,ro",
+            "bind:/src:/home/user/src,ro",
+            "dir:/home/user/",
+            "chmod:0700:/home/user",
+            "tmpfs:/home",
+            "bind:/usr:/usr,ro",
+        ])
+        .unwrap();
+
+        let expected = [
+            "/home",
+            "/usr",
+            "/home/user",
+            "/home/user",
+            "/home/user/src",
+            "/usr",
+        ];
+        assert_eq!(order, expected.map(PathBuf::from));
+    }
+
+    #[test]
+    fn test_plan_keeps_chmod_before_later_mounts() {
+        let specs: Vec<MountSpec> = ["dir:/data", "chmod:0700:/data", "tmpfs:/data"]
+            .iter()
+            .map(|s| s.parse().unwrap())
+            .collect();
+        let kinds: Vec<&str> = MountPlan::new(&specs)
+            .unwrap()
+            .into_specs()
+            .iter()
+            .map(|spec| spec.entry.kind())
+            .collect();
+
+        assert_eq!(kinds, ["dir", "chmod", "tmpfs"]);
+    }
+
+    #[test]
+    fn test_plan_rejects_invalid_targets() {
+        assert!(matches!(
+            plan(&["tmpfs:tmp"]),
+            Err(PlanError::RelativeDestination { .. })
+        ));
+        assert!(matches!(
+            plan(&["tmpfs:/tmp/../etc"]),
+            Err(PlanError::ParentComponent { .. })
+        ));
+        assert!(matches!(
+            plan(&["tmpfs:/tmp", "bind:/var/tmp:/tmp/./,rw"]),
+            Err(PlanError::DuplicateTarget { .. })
+        ));
+        assert!(matches!(
+            plan(&["tmpfs:/"]),
+            Err(PlanError::RootDestination { .. })
+        ));
+        assert!(matches!(
+            plan(&["bind:/srv:/./,ro"]),
+            Err(PlanError::RootDestination { .. })
+        ));
+
+        // A remount or a directory at a mount's destination is expected
+        assert!(plan(&["tmpfs:/tmp", "dir:/tmp", "remount:/tmp,ro"]).is_ok());
+        assert!(plan(&["chmod:0755:/", "remount:/,ro"]).is_ok());
+    }
+}
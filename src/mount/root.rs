@@ -0,0 +1,281 @@
+use nix::{
+    NixPath,
+    errno::Errno,
+    fcntl::{AT_FDCWD, AtFlags, OFlag, OpenHow, ResolveFlag, open, openat2, readlinkat},
+    libc,
+    sys::stat::{
+        FchmodatFlags, FileStat, Mode, SFlag, fchmod, fchmodat, fstat, fstatat, mkdirat, mknodat,
+    },
+    unistd::{UnlinkatFlags, symlinkat, unlinkat},
+};
+use std::{
+    ffi::OsStr,
+    os::fd::{AsRawFd, OwnedFd},
+    path::{Component, Path, PathBuf},
+};
+
+/// The new root as a directory fd, every path below it is resolved w/ `openat2(2)`.
+///
+/// Resolution is scoped to the root (`RESOLVE_IN_ROOT`), so absolute symlinks and '..'
+/// can't leave it, and magic links like '/proc/self/fd/<N>' are refused outright.
+#[derive(Debug)]
+pub struct RootFd {
+    fd: OwnedFd,
+}
+
+impl RootFd {
+    pub fn open<P: ?Sized + NixPath>(path: &P) -> nix::Result<Self> {
+        let fd = open(
+            path,
+            OFlag::O_PATH | OFlag::O_DIRECTORY | OFlag::O_CLOEXEC,
+            Mode::empty(),
+        )?;
+        Ok(Self { fd })
+    }
+
+    pub fn resolve(&self, path: &Path, flags: OFlag, mode: Mode) -> nix::Result<OwnedFd> {
+        let how = OpenHow::new()
+            .flags(flags | OFlag::O_CLOEXEC)
+            .mode(mode)
+            .resolve(ResolveFlag::RESOLVE_IN_ROOT | ResolveFlag::RESOLVE_NO_MAGICLINKS);
+
+        openat2(&self.fd, relative(path), how)
+    }
+
+    /// `O_PATH` handle, enough to mount on, `fstat` or `mount_setattr` it.
+    pub fn open_path(&self, path: &Path) -> nix::Result<OwnedFd> {
+        self.resolve(path, OFlag::O_PATH, Mode::empty())
+    }
+
+    /// Like `mkdir -p`, `mode` only applies to the directories it creates.
+    pub fn ensure_dir(&self, path: &Path, mode: u32) -> nix::Result<OwnedFd> {
+        let flags = OFlag::O_PATH | OFlag::O_DIRECTORY;
+        let mode = Mode::from_bits_truncate(mode);
+
+        let mut current = PathBuf::from("/");
+        let mut dir = self.resolve(&current, flags, Mode::empty())?;
+
+        for component in path.components() {
+            match component {
+                Component::Normal(name) => match mkdirat(&dir, name, mode) {
+                    Ok(()) | Err(Errno::EEXIST) => {}
+                    Err(e) => return Err(e),
+                },
+                Component::ParentDir => {}
+                _ => continue,
+            }
+
+            // Re-resolved from the root each time, a symlinked component stays inside it
+            current.push(component);
+            dir = self.resolve(&current, flags, Mode::empty())?;
+        }
+
+        Ok(dir)
+    }
+
+    /// Creates an empty regular file unless something other than a directory is already
+    /// there, returns an `O_PATH` handle to it.
+    pub fn ensure_file(&self, path: &Path, mode: u32) -> nix::Result<OwnedFd> {
+        let (parent, name) = self.parent_of(path, true)?;
+
+        match mknodat(
+            &parent,
+            name,
+            SFlag::S_IFREG,
+            Mode::from_bits_truncate(mode),
+            0,
+        ) {
+            Ok(()) | Err(Errno::EEXIST) => {}
+            Err(e) => return Err(e),
+        }
+
+        let fd = self.open_path(path)?;
+        match SFlag::from_bits_truncate(fstat(&fd)?.st_mode) & SFlag::S_IFMT {
+            SFlag::S_IFDIR => Err(Errno::EISDIR),
+            _ => Ok(fd),
+        }
+    }
+
+    /// Creates the parent directories of `link` as needed.
+    pub fn symlink(&self, target: &Path, link: &Path) -> nix::Result<()> {
+        let (parent, name) = self.parent_of(link, true)?;
+        symlinkat(target, &parent, name)
+    }
+
+    pub fn read_link(&self, link: &Path) -> nix::Result<PathBuf> {
+        let (parent, name) = self.parent_of(link, false)?;
+        readlinkat(&parent, name).map(PathBuf::from)
+    }
+
+    pub fn unlink(&self, path: &Path) -> nix::Result<()> {
+        let (parent, name) = self.parent_of(path, false)?;
+        unlinkat(&parent, name, UnlinkatFlags::NoRemoveDir)
+    }
+
+    /// Doesn't follow a symlink in the last component.
+    pub fn lstat(&self, path: &Path) -> nix::Result<FileStat> {
+        let (parent, name) = self.parent_of(path, false)?;
+        fstatat(&parent, name, AtFlags::AT_SYMLINK_NOFOLLOW)
+    }
+
+    /// Follows symlinks, as long as they resolve inside the root.
+    ///
+    /// `proc` is only used w/o `fchmodat2()`, to reach sockets, FIFOs and devices.
+    pub fn chmod(&self, path: &Path, mode: u32, proc: &Path) -> nix::Result<()> {
+        let fd = self.open_path(path)?;
+
+        // An O_PATH fd can't be fchmod()ed, fchmodat2() takes it w/ AT_EMPTY_PATH
+        let res = unsafe {
+            libc::syscall(
+                libc::SYS_fchmodat2,
+                fd.as_raw_fd(),
+                c"".as_ptr(),
+                mode as libc::mode_t,
+                libc::AT_EMPTY_PATH,
+            )
+        };
+
+        match Errno::result(res) {
+            // Added in Linux 6.6
+            Err(Errno::ENOSYS) => {
+                let stat = fstat(&fd)?;
+                match SFlag::from_bits_truncate(stat.st_mode) & SFlag::S_IFMT {
+                    SFlag::S_IFREG | SFlag::S_IFDIR => self.chmod_reopened(path, &stat, mode),
+                    _ => chmod_magic_link(proc, &fd, mode),
+                }
+            }
+            res => res.map(drop),
+        }
+    }
+
+    /// `fchmod()` on a readable reopen of `path`, as long as it's still `expected`.
+    fn chmod_reopened(&self, path: &Path, expected: &FileStat, mode: u32) -> nix::Result<()> {
+        let reopened = self.resolve(
+            path,
+            OFlag::O_RDONLY | OFlag::O_NONBLOCK | OFlag::O_NOCTTY,
+            Mode::empty(),
+        )?;
+        let stat = fstat(&reopened)?;
+
+        // Swapped out in between, don't touch whatever took its place
+        if (stat.st_dev, stat.st_ino) != (expected.st_dev, expected.st_ino) {
+            return Err(Errno::ESTALE);
+        }
+        fchmod(&reopened, Mode::from_bits_truncate(mode as libc::mode_t))
+    }
+
+    /// Directory holding the last component of `path`, along w/ that component.
+    fn parent_of<'p>(&self, path: &'p Path, create: bool) -> nix::Result<(OwnedFd, &'p OsStr)> {
+        let name = path.file_name().ok_or(Errno::EINVAL)?;
+        let parent = path.parent().unwrap_or(Path::new("/"));
+
+        let dir = match create {
+            true => self.ensure_dir(parent, 0o755)?,
+            false => self.resolve(parent, OFlag::O_PATH | OFlag::O_DIRECTORY, Mode::empty())?,
+        };
+        Ok((dir, name))
+    }
+}
+
+/// Sockets, FIFOs and devices can't be reopened w/o side effects, so they're changed through
+/// the magic link of their `O_PATH` handle in `proc`. The staging root has no '/proc', and
+/// `fchmodat(AT_SYMLINK_NOFOLLOW)` only emulates this through it.
+///
+/// `EOPNOTSUPP` if `proc` isn't a procfs that lists the fd.
+fn chmod_magic_link(proc: &Path, fd: &OwnedFd, mode: u32) -> nix::Result<()> {
+    let link = proc.join(format!("self/fd/{}", fd.as_raw_fd()));
+
+    match fchmodat(
+        AT_FDCWD,
+        &link,
+        Mode::from_bits_truncate(mode as libc::mode_t),
+        FchmodatFlags::FollowSymlink,
+    ) {
+        Err(Errno::ENOENT | Errno::ENOTDIR) => Err(Errno::EOPNOTSUPP),
+        res => res,
+    }
+}
+
+/// `openat2(2)` wants paths relative to the dirfd, the root itself is '.'.
+fn relative(path: &Path) -> &Path {
+    match path.strip_prefix("/").unwrap_or(path) {
+        rel if rel.as_os_str().is_empty() => Path::new("."),
+        rel => rel,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolution_stays_in_root() {
+        let base = std::env::temp_dir().join(format!("enclosure-root-{}", std::process::id()));
+        let root_dir = base.join("root");
+        std::fs::create_dir_all(&root_dir).unwrap();
+        std::os::unix::fs::symlink("../../..", root_dir.join("up")).unwrap();
+        std::os::unix::fs::symlink(&base, root_dir.join("abs")).unwrap();
+
+        let root = RootFd::open(&root_dir).unwrap();
+        root.ensure_dir(Path::new("/up/escaped"), 0o755).unwrap();
+        root.ensure_file(Path::new("/up/file"), 0o644).unwrap();
+        root.symlink(Path::new("x"), Path::new("/up/link")).unwrap();
+
+        assert!(root_dir.join("escaped").is_dir());
+        assert!(root_dir.join("file").is_file());
+        assert!(root_dir.join("link").symlink_metadata().is_ok());
+        assert!(!base.join("escaped").exists());
+
+        // The absolute target is re-rooted, and doesn't exist inside
+        assert_eq!(
+            root.open_path(Path::new("/abs/root")).unwrap_err(),
+            Errno::ENOENT
+        );
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_chmod_reopened_fallback() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let base = std::env::temp_dir().join(format!("enclosure-chmod-{}", std::process::id()));
+        std::fs::create_dir_all(base.join("dir")).unwrap();
+        std::fs::write(base.join("file"), "").unwrap();
+
+        let root = RootFd::open(&base).unwrap();
+        for (path, mode) in [("/dir", 0o711), ("/file", 0o640)] {
+            let stat = fstat(root.open_path(Path::new(path)).unwrap()).unwrap();
+            root.chmod_reopened(Path::new(path), &stat, mode).unwrap();
+
+            let meta = std::fs::metadata(base.join(&path[1..])).unwrap();
+            assert_eq!(meta.permissions().mode() & 0o7777, mode);
+        }
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+
+    #[test]
+    fn test_chmod_magic_link_fallback() {
+        use std::os::unix::{fs::PermissionsExt, net::UnixListener};
+
+        let base = std::env::temp_dir().join(format!("enclosure-sock-{}", std::process::id()));
+        std::fs::create_dir_all(&base).unwrap();
+        let _listener = UnixListener::bind(base.join("app.sock")).unwrap();
+
+        let root = RootFd::open(&base).unwrap();
+        let fd = root.open_path(Path::new("/app.sock")).unwrap();
+        chmod_magic_link(Path::new("/proc"), &fd, 0o600).unwrap();
+
+        let meta = std::fs::symlink_metadata(base.join("app.sock")).unwrap();
+        assert_eq!(meta.permissions().mode() & 0o7777, 0o600);
+
+        // W/o a procfs there's no way to reach it
+        assert_eq!(
+            chmod_magic_link(&base, &fd, 0o600).unwrap_err(),
+            Errno::EOPNOTSUPP
+        );
+
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
@@ -0,0 +1,250 @@
+use super::info::{MountInfo, MountInfoError, MountLine};
+use std::{collections::HashMap, fmt, io::Read, path::Path};
+
+#[derive(Debug, thiserror::Error)]
+pub enum TreeError {
+    #[error("mount table is empty")]
+    Empty,
+
+    #[error("mount ID {0} is listed twice")]
+    DuplicateId(u32),
+
+    #[error("no mount w/o a parent, the table has no root")]
+    NoRoot,
+
+    #[error("failed to read mount table")]
+    Info(#[from] MountInfoError),
+}
+
+/// Mounts linked by their parent IDs, as seen from one mount namespace.
+///
+/// Several mounts can share a mountpoint, the later one overmounts the earlier and
+/// becomes its child. Only the top of such a stack is visible.
+#[derive(Debug, Clone)]
+pub struct MountTree {
+    mounts: Vec<MountLine>,
+    index: HashMap<u32, usize>,
+    children: Vec<Vec<usize>>,
+    root: usize,
+}
+
+impl MountTree {
+    /// `lines` in mountinfo order, i.e. roughly the order they were mounted in.
+    pub fn new(lines: impl IntoIterator<Item = MountLine>) -> Result<Self, TreeError> {
+        let mounts: Vec<MountLine> = lines.into_iter().collect();
+        if mounts.is_empty() {
+            return Err(TreeError::Empty);
+        }
+
+        let mut index = HashMap::with_capacity(mounts.len());
+        for (idx, mount) in mounts.iter().enumerate() {
+            if index.insert(mount.id, idx).is_some() {
+                return Err(TreeError::DuplicateId(mount.id));
+            }
+        }
+
+        let mut children = vec![Vec::new(); mounts.len()];
+        let mut roots = Vec::new();
+        for (idx, mount) in mounts.iter().enumerate() {
+            match index.get(&mount.parent_id) {
+                Some(&parent) if parent != idx => children[parent].push(idx),
+                // The parent is outside our root, or it's the namespace's own root
+                _ => roots.push(idx),
+            }
+        }
+
+        let root = roots
+            .iter()
+            .copied()
+            .find(|&idx| mounts[idx].mountpoint == Path::new("/"))
+            .or(roots.first().copied())
+            .ok_or(TreeError::NoRoot)?;
+
+        Ok(Self {
+            mounts,
+            index,
+            children,
+            root,
+        })
+    }
+
+    pub fn root(&self) -> &MountLine {
+        &self.mounts[self.root]
+    }
+
+    pub fn get(&self, id: u32) -> Option<&MountLine> {
+        self.index.get(&id).map(|&idx| &self.mounts[idx])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &MountLine> {
+        self.mounts.iter()
+    }
+
+    /// Direct children of the mount `id`, overmounts included.
+    pub fn children(&self, id: u32) -> impl Iterator<Item = &MountLine> {
+        self.index
+            .get(&id)
+            .into_iter()
+            .flat_map(|&idx| self.children[idx].iter().map(|&child| &self.mounts[child]))
+    }
+
+    /// The visible mount a lookup of `path` ends up on.
+    pub fn covering(&self, path: &Path) -> &MountLine {
+        let mut current = self.top(self.root);
+
+        loop {
+            let mountpoint = &self.mounts[current].mountpoint;
+
+            // A shallower sibling was mounted later, otherwise it'd be the parent, so it hides the rest
+            let next = self.children[current]
+                .iter()
+                .rev()
+                .copied()
+                .filter(|&child| {
+                    let child = &self.mounts[child].mountpoint;
+                    child != mountpoint && path.starts_with(child)
+                })
+                .min_by_key(|&child| self.mounts[child].mountpoint.components().count());
+
+            match next {
+                Some(child) => current = self.top(child),
+                None => return &self.mounts[current],
+            }
+        }
+    }
+
+    /// The visible mount whose mountpoint is exactly `mountpoint`.
+    pub fn at(&self, mountpoint: &Path) -> Option<&MountLine> {
+        Some(self.covering(mountpoint)).filter(|mount| mount.mountpoint == mountpoint)
+    }
+
+    pub fn is_visible(&self, id: u32) -> bool {
+        self.get(id)
+            .is_some_and(|mount| self.covering(&mount.mountpoint).id == id)
+    }
+
+    /// Mounts hidden by another one on top of them, or on top of one of their parents.
+    pub fn overmounted(&self) -> impl Iterator<Item = &MountLine> {
+        self.mounts
+            .iter()
+            .filter(|mount| !self.is_visible(mount.id))
+    }
+
+    /// Last mount stacked on `idx` at the same mountpoint.
+    fn top(&self, mut idx: usize) -> usize {
+        loop {
+            let mountpoint = &self.mounts[idx].mountpoint;
+            match self.children[idx]
+                .iter()
+                .rev()
+                .find(|&&child| self.mounts[child].mountpoint == *mountpoint)
+            {
+                Some(&child) => idx = child,
+                None => return idx,
+            }
+        }
+    }
+
+    fn fmt_mount(&self, f: &mut fmt::Formatter<'_>, idx: usize, depth: usize) -> fmt::Result {
+        let mount = &self.mounts[idx];
+        write!(
+            f,
+            "{:indent$}{} {} {} {}",
+            "",
+            mount.mountpoint.display(),
+            mount.fstype,
+            mount.source,
+            describe_options(mount),
+            indent = depth * 2
+        )?;
+
+        if !self.is_visible(mount.id) {
+            write!(f, " (overmounted)")?;
+        }
+        writeln!(f)?;
+
+        self.children[idx]
+            .iter()
+            .try_for_each(|&child| self.fmt_mount(f, child, depth + 1))
+    }
+}
+
+impl<R: Read> TryFrom<MountInfo<R>> for MountTree {
+    type Error = TreeError;
+
+    fn try_from(info: MountInfo<R>) -> Result<Self, Self::Error> {
+        let lines = info.into_iter().collect::<Result<Vec<_>, _>>()?;
+        Self::new(lines)
+    }
+}
+
+/// Layout w/ one mount per line, indented below its parent.
+impl fmt::Display for MountTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_mount(f, self.root, 0)
+    }
+}
+
+fn describe_options(mount: &MountLine) -> String {
+    std::iter::once(mount.options.to_string())
+        .chain(mount.extra_options.iter().cloned())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // '/data' got overmounted by a second tmpfs, hiding '/data/cache' below the first
+    const OVERMOUNTED: &str = "\
+1 0 0:30 / / rw,relatime - tmpfs tmpfs rw
+2 1 254:0 /usr /usr ro,nosuid,nodev,relatime - ext4 /dev/vda rw
+3 1 0:31 / /data rw,nosuid,nodev,relatime - tmpfs tmpfs rw
+4 3 254:0 /srv/cache /data/cache rw,nosuid,nodev,relatime - ext4 /dev/vda rw
+5 3 0:32 / /data rw,nosuid,nodev,noexec,relatime - tmpfs tmpfs rw,size=1024k
+6 5 0:33 / /data/run rw,nosuid,nodev,relatime - tmpfs tmpfs rw
+7 2 254:0 /usr/lib /usr/lib ro,nosuid,nodev,relatime - ext4 /dev/vda rw
+";
+
+    fn tree(table: &str) -> MountTree {
+        MountTree::try_from(MountInfo::from_reader(table.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn test_tree_queries() {
+        let tree = tree(OVERMOUNTED);
+        assert_eq!(tree.root().id, 1);
+
+        let children: Vec<u32> = tree.children(3).map(|mount| mount.id).collect();
+        assert_eq!(children, [4, 5]);
+
+        assert_eq!(tree.covering(Path::new("/data/cache/x")).id, 5);
+        assert_eq!(tree.covering(Path::new("/data/run/x")).id, 6);
+        assert_eq!(tree.covering(Path::new("/usr/lib/libc.so")).id, 7);
+        assert_eq!(tree.covering(Path::new("/etc")).id, 1);
+
+        assert_eq!(tree.at(Path::new("/data")).map(|mount| mount.id), Some(5));
+        assert!(tree.at(Path::new("/data/cache")).is_none());
+
+        let hidden: Vec<u32> = tree.overmounted().map(|mount| mount.id).collect();
+        assert_eq!(hidden, [3, 4]);
+
+        let layout = tree.to_string();
+        assert!(layout.starts_with("/ tmpfs tmpfs rw,relatime\n"));
+        assert!(layout.contains("\n  /data tmpfs tmpfs rw,nosuid,nodev,relatime (overmounted)\n"));
+        assert!(layout.contains("\n      /data/run tmpfs tmpfs rw,nosuid,nodev,relatime\n"));
+    }
+
+    #[test]
+    fn test_tree_rejects_invalid() {
+        assert!(matches!(MountTree::new([]), Err(TreeError::Empty)));
+        assert!(matches!(
+            MountTree::new([
+                "1 0 0:30 / / rw - tmpfs tmpfs rw".parse().unwrap(),
+                "1 0 0:31 / /x rw - tmpfs tmpfs rw".parse().unwrap(),
+            ]),
+            Err(TreeError::DuplicateId(1))
+        ));
+    }
+}
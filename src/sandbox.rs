@@ -2,20 +2,21 @@ use crate::{
     capabilities::{
         CapabilityBuilder, CapabilityManager, SETUID_CAPABILITIES, apply_setuid_capabilities,
     },
-    config::Config,
+    config::{Config, IdMap},
     context::{Parent, PrivilegeLevel, ProcessContext, ROOTLESS_WITH_CAPABILITY_ERROR_MESSAGE},
     jail::Jail,
     jailer::{ExitHandler, HostResource, JailHandle, Jailer},
-    utils,
+    mount::{bind::IdmappedSources, plan::MountPlan},
+    utils::{self, IdentityMap},
 };
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 use nix::{
     fcntl::OFlag,
     sched::{CloneFlags, setns},
     sys::stat::Mode,
     unistd::{Gid, Uid},
 };
-use std::os::fd::AsFd;
+use std::os::fd::{AsFd, BorrowedFd};
 
 mod sealed {
     pub trait Sealed {}
@@ -79,10 +80,20 @@ impl Sandbox<Configured> {
         })
     }
 
-    pub fn spawn_jail(self) -> Result<Sandbox<Spawned>> {
+    pub fn spawn_jail(mut self) -> Result<Sandbox<Spawned>> {
         utils::apply_no_new_privs()?;
 
+        // Entry indices below refer to the planned order, the child applies it as is
+        self.config.mount = MountPlan::new(&self.config.mount)
+            .context("Invalid mount plan")?
+            .into_specs();
+
         let flags = self.config.parse_clone_flags()?;
+        let proc_fd = nix::fcntl::open("/proc", OFlag::O_PATH, Mode::empty())?;
+
+        // Joining another user namespace gives up CAP_SYS_ADMIN over the host, idmap first
+        let idmapped = self.idmap_sources(proc_fd.as_fd())?;
+
         if let Some(fd) = self.config.user.userns {
             utils::with_raw_fd(fd, |borrowed_fd| {
                 setns(borrowed_fd, flags)?;
@@ -90,8 +101,8 @@ impl Sandbox<Configured> {
             })?;
         };
 
-        let proc_fd = nix::fcntl::open("/proc", OFlag::O_PATH, Mode::empty())?;
-        let jail = Jail::new(self.config.clone(), HostResource::new(proc_fd.as_fd()));
+        let resource = HostResource::new(proc_fd.as_fd()).with_idmapped(&idmapped);
+        let jail = Jail::new(self.config.clone(), resource);
 
         let handle = Jailer::new(jail)
             .with_clone_flags(flags)
@@ -104,6 +115,93 @@ impl Sandbox<Configured> {
             state: Spawned { handle },
         })
     }
+
+    /// Clones and ID-maps the sources of every `idmap` entry, the child only attaches them.
+    fn idmap_sources(&self, proc_fd: BorrowedFd<'_>) -> Result<IdmappedSources> {
+        let mut idmapped = IdmappedSources::default();
+        let sources = self.config.idmapped_sources();
+        if sources.is_empty() {
+            return Ok(idmapped);
+        }
+
+        // SAFETY: parent context is initialized in main()
+        let context = unsafe { ProcessContext::<Parent>::get() };
+        check_idmap_privilege(context.privilege_level())?;
+
+        let sandbox = match sources.iter().any(|(.., idmap)| **idmap == IdMap::Sandbox) {
+            true if !self.config.namespace.unshare_user => {
+                bail!("'idmap' w/o a user namespace path needs --unshare-user")
+            }
+            true => Some(
+                utils::userns_with_map(self.identity_map(), proc_fd)
+                    .context("Failed to create a user namespace w/ the sandbox's mapping")?,
+            ),
+            false => None,
+        };
+
+        for (idx, src, idmap) in sources {
+            // Would ID-map every file on the host
+            if src.parent().is_none() {
+                bail!("Refusing to idmap '/', pick a directory below it");
+            }
+
+            let custom;
+            let userns = match (idmap, &sandbox) {
+                (IdMap::Sandbox, Some(userns)) => userns.as_fd(),
+                (IdMap::Sandbox, None) => unreachable!("created whenever an entry asks for it"),
+                (IdMap::Userns(path), _) => {
+                    custom =
+                        nix::fcntl::open(path, OFlag::O_RDONLY | OFlag::O_CLOEXEC, Mode::empty())
+                            .with_context(|| {
+                            format!("Failed to open user namespace {}", path.display())
+                        })?;
+                    custom.as_fd()
+                }
+            };
+
+            idmapped.capture(idx, &src, userns).with_context(|| {
+                format!(
+                    "Failed to idmap {}, its filesystem may not support idmapped mounts",
+                    src.display()
+                )
+            })?;
+        }
+
+        Ok(idmapped)
+    }
+}
+
+/// Only root may idmap, a setuid caller could map a source they can't write to their own IDs.
+fn check_idmap_privilege(level: PrivilegeLevel) -> Result<()> {
+    match level {
+        PrivilegeLevel::Root => Ok(()),
+        PrivilegeLevel::Setuid => {
+            bail!("'idmap' isn't allowed in setuid mode, it would expose sources as the caller")
+        }
+        _ => bail!("'idmap' needs CAP_SYS_ADMIN over the host filesystems, run as root"),
+    }
+}
+
+impl<S: sealed::Sealed> Sandbox<S> {
+    /// Mapping of the sandbox's user namespace, defaults match the ones the writers use.
+    fn identity_map(&self) -> IdentityMap {
+        // SAFETY: parent context is initialized in main()
+        let context = unsafe { ProcessContext::<Parent>::get() };
+
+        // A setuid launch keeps the caller's IDs inside, otherwise it's root
+        let (uid, gid) = match context.setuid() {
+            true => (context.ruid(), context.guid()),
+            false => (Uid::from_raw(0), Gid::from_raw(0)),
+        };
+
+        IdentityMap::new(
+            self.config.user.uid.map(Uid::from).unwrap_or(uid),
+            self.config.user.gid.map(Gid::from).unwrap_or(gid),
+            context.ruid(),
+            context.guid(),
+            context.overflow_ids(),
+        )
+    }
 }
 
 impl Sandbox<Spawned> {
@@ -113,29 +211,7 @@ impl Sandbox<Spawned> {
         let context = unsafe { ProcessContext::<Parent>::get() };
 
         if context.setuid() && self.config.namespace.unshare_user {
-            let namespace_uid = self
-                .config
-                .user
-                .uid
-                .map(Uid::from)
-                .unwrap_or_else(|| context.ruid());
-
-            let namespace_gid = self
-                .config
-                .user
-                .gid
-                .map(Gid::from)
-                .unwrap_or_else(|| context.guid());
-
-            let map = utils::IdentityMap::new(
-                namespace_uid,
-                namespace_gid,
-                context.ruid(),
-                context.guid(),
-                context.overflow_ids(),
-            );
-
-            let writer = utils::ExternalWriter::new(child_pid, map);
+            let writer = utils::ExternalWriter::new(child_pid, self.identity_map());
 
             let proc_fd = nix::fcntl::open("/proc", OFlag::O_PATH, Mode::empty())?;
             writer.write(proc_fd.as_fd())?;
@@ -167,3 +243,18 @@ impl Sandbox<Launched> {
         Ok(handler)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_idmap_needs_root() {
+        assert!(check_idmap_privilege(PrivilegeLevel::Root).is_ok());
+
+        for level in [PrivilegeLevel::Setuid, PrivilegeLevel::Rootless] {
+            let err = check_idmap_privilege(level).unwrap_err();
+            assert!(err.to_string().contains("'idmap'"), "{level:?}: {err}");
+        }
+    }
+}
@@ -4,19 +4,23 @@ use memmap2::{MmapMut, MmapOptions};
 use nix::{
     fcntl::{FcntlArg, OFlag, fcntl, openat},
     libc::{PR_SET_NO_NEW_PRIVS, PROT_NONE, mprotect, prctl},
-    sched::CloneFlags,
-    sys::stat::Mode,
-    sys::utsname::uname,
-    unistd::{Gid, Pid, SysconfVar, Uid, setfsuid, sysconf},
+    sched::{CloneFlags, clone},
+    sys::{
+        signal::{Signal, kill},
+        stat::Mode,
+        utsname::uname,
+        wait::waitpid,
+    },
+    unistd::{Gid, Pid, SysconfVar, Uid, pause, setfsuid, sysconf},
 };
 use std::{
     fs::{DirBuilder, File, Permissions, create_dir_all},
     io::{ErrorKind, Write},
     os::{
-        fd::{AsFd, AsRawFd, BorrowedFd},
+        fd::{AsFd, AsRawFd, BorrowedFd, OwnedFd},
         unix::fs::{DirBuilderExt, PermissionsExt},
     },
-    path::{Path, PathBuf},
+    path::{Component, Path, PathBuf},
 };
 
 pub(crate) fn apply_no_new_privs() -> Result<()> {
@@ -52,9 +56,48 @@ pub(crate) fn setuid_restrict_fs_privileges() -> Result<()> {
     Ok(())
 }
 
-pub(crate) fn resolve_path(root: &Path, path: &Path) -> PathBuf {
-    let stripped = path.strip_prefix("/").unwrap_or(path);
-    root.join(stripped)
+/// Joins `path` onto `root`, following symlinks as if `root` was '/'.
+///
+/// Absolute link targets restart from `root` and '..' never climbs above it. Missing
+/// components are kept as is, so the caller still gets a meaningful error.
+pub(crate) fn resolve_in_root(root: &Path, path: &Path) -> PathBuf {
+    // Same limit as the kernel's MAXSYMLINKS
+    const MAX_LINKS: usize = 40;
+
+    let mut pending: Vec<PathBuf> = path
+        .components()
+        .rev()
+        .map(|c| c.as_os_str().into())
+        .collect();
+    let mut resolved = PathBuf::new();
+    let mut links = 0;
+
+    while let Some(part) = pending.pop() {
+        match part.components().next() {
+            Some(Component::Normal(name)) => {
+                let candidate = resolved.join(name);
+                let full = root.join(&candidate);
+
+                match std::fs::read_link(&full) {
+                    Ok(target) if links < MAX_LINKS => {
+                        links += 1;
+                        if target.is_absolute() {
+                            resolved.clear();
+                        }
+                        pending.extend(target.components().rev().map(|c| c.as_os_str().into()));
+                    }
+                    // Either not a symlink, or a loop the kernel will report on its own
+                    _ => resolved = candidate,
+                }
+            }
+            Some(Component::ParentDir) => {
+                resolved.pop();
+            }
+            _ => {}
+        }
+    }
+
+    root.join(resolved)
 }
 
 /// Executes a closure with a borrowed FD while ensuring that the provided FD is valid.
@@ -178,6 +221,30 @@ pub fn ensure_dir<P: AsRef<Path>>(path: P) -> Result<()> {
     ensure_dir_with_mode(path, 0o755)
 }
 
+/// Whether `fd` refers to the root of a mount.
+pub fn is_mountpoint(fd: BorrowedFd<'_>) -> Result<bool> {
+    let mut stx = std::mem::MaybeUninit::<nix::libc::statx>::zeroed();
+
+    let res = unsafe {
+        nix::libc::statx(
+            fd.as_raw_fd(),
+            c"".as_ptr(),
+            nix::libc::AT_EMPTY_PATH,
+            0,
+            stx.as_mut_ptr(),
+        )
+    };
+    nix::errno::Errno::result(res).context("Failed to statx mountpoint")?;
+
+    // SAFETY: statx succeeded, so the buffer is initialized
+    let stx = unsafe { stx.assume_init() };
+    if stx.stx_attributes_mask & nix::libc::STATX_ATTR_MOUNT_ROOT as u64 == 0 {
+        bail!("Kernel doesn't report mount roots (STATX_ATTR_MOUNT_ROOT)");
+    }
+
+    Ok(stx.stx_attributes & nix::libc::STATX_ATTR_MOUNT_ROOT as u64 != 0)
+}
+
 pub fn is_namespace_supported(flag: CloneFlags) -> bool {
     fn exists(ns: &str) -> bool {
         Path::new(&format!("/proc/self/ns/{}", ns)).exists()
@@ -205,8 +272,9 @@ pub fn is_namespace_supported(flag: CloneFlags) -> bool {
     }
 }
 
-pub(crate) fn is_cgroups_supported() -> bool {
-    Path::new("/proc/self/ns/cgroup").exists()
+/// `proc` is where a procfs is mounted, e.g. '/proc'.
+pub(crate) fn is_cgroups_supported(proc: &Path) -> bool {
+    proc.join("self/ns/cgroup").exists()
 }
 
 /// A (mmap'ed) stack allocation with a guard page.
@@ -325,6 +393,41 @@ impl IdentityMap {
     }
 }
 
+/// A user namespace w/ `map` written to it, held only long enough to open it.
+///
+/// Mount idmappings copy the namespace's mapping, so the helper process that owns it
+/// is reaped before returning.
+pub fn userns_with_map(map: IdentityMap, proc_fd: BorrowedFd<'_>) -> Result<OwnedFd> {
+    let mut stack = GuardedStack::new(page_size()? * 16)?;
+
+    let pid = unsafe {
+        clone(
+            Box::new(|| {
+                loop {
+                    pause();
+                }
+            }),
+            stack.as_mut_slice(),
+            CloneFlags::CLONE_NEWUSER,
+            Some(Signal::SIGCHLD as i32),
+        )
+    }
+    .context("Failed to spawn user namespace helper")?;
+
+    let userns = ExternalWriter::new(pid, map).write(proc_fd).and_then(|()| {
+        let dir = Dir::from(proc_fd);
+        Ok(dir.open_with(
+            &format!("{pid}/ns/user"),
+            OFlag::O_RDONLY | OFlag::O_CLOEXEC,
+        )?)
+    });
+
+    kill(pid, Signal::SIGKILL)?;
+    waitpid(pid, None)?;
+
+    Ok(OwnedFd::from(userns?))
+}
+
 fn write_proc_map_file(parent: &File, name: &str, content: &str) -> Result<()> {
     let dir = Dir::from(parent.as_fd());
     let mut file = dir.open_with(name, OFlag::O_WRONLY | OFlag::O_CLOEXEC)?;
@@ -391,4 +494,32 @@ mod tests {
             result.err()
         );
     }
+
+    #[test]
+    fn test_resolve_in_root() {
+        let root = std::env::temp_dir().join(format!("enclosure-resolve-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("usr/lib64")).unwrap();
+        std::os::unix::fs::symlink("/usr/lib64", root.join("abs")).unwrap();
+        std::os::unix::fs::symlink("usr/lib64", root.join("rel")).unwrap();
+        std::os::unix::fs::symlink("../../..", root.join("usr/up")).unwrap();
+
+        assert_eq!(
+            resolve_in_root(&root, Path::new("/abs")),
+            root.join("usr/lib64")
+        );
+        assert_eq!(
+            resolve_in_root(&root, Path::new("/rel/x")),
+            root.join("usr/lib64/x")
+        );
+        assert_eq!(
+            resolve_in_root(&root, Path::new("/usr/up/etc")),
+            root.join("etc")
+        );
+        assert_eq!(
+            resolve_in_root(&root, Path::new("/missing")),
+            root.join("missing")
+        );
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
 }